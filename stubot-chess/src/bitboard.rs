@@ -0,0 +1,244 @@
+use super::*;
+
+// one bit per square, bit index is y * 8 + x (a1 = 0, h8 = 63)
+pub type BitBoard = u64;
+
+pub fn pos_idx(pos: Pos) -> usize {
+    (pos.y * BOARD_DIM.x + pos.x) as usize
+}
+pub fn idx_pos(idx: usize) -> Pos {
+    let dim = BOARD_DIM.x as usize;
+    Pos {
+        y: (idx / dim) as i8,
+        x: (idx % dim) as i8,
+    }
+}
+pub fn pos_bit(pos: Pos) -> BitBoard {
+    1 << pos_idx(pos)
+}
+pub fn rank_bits(y: i8) -> BitBoard {
+    0xff << (y * BOARD_DIM.x)
+}
+pub fn file_bits(x: i8) -> BitBoard {
+    0x0101_0101_0101_0101 << x
+}
+// the files either side of x
+pub fn adjacent_files(x: i8) -> BitBoard {
+    let left = if x > 0 { file_bits(x - 1) } else { 0 };
+    let right = if x < BOARD_DIM.x - 1 {
+        file_bits(x + 1)
+    } else {
+        0
+    };
+    left | right
+}
+// the ranks in front of y from clr's side
+pub fn ranks_ahead(clr: Color, y: i8) -> BitBoard {
+    match clr {
+        Color::White => (!0 as BitBoard)
+            .checked_shl(((y + 1) * BOARD_DIM.x) as u32)
+            .unwrap_or(0),
+        Color::Black => (1 << (y * BOARD_DIM.x)) - 1,
+    }
+}
+// squares from a to b, inclusive. both should be on the same rank
+pub fn rank_span(a: Pos, b: Pos) -> BitBoard {
+    let (a, b) = (pos_idx(a), pos_idx(b));
+    let (lo, hi) = (a.min(b), a.max(b));
+    (!0 >> (63 - hi)) & (!0 << lo)
+}
+
+// iterates over the set bits, lowest first
+pub struct BitIter(pub BitBoard);
+impl Iterator for BitIter {
+    type Item = Pos;
+    fn next(&mut self) -> Option<Pos> {
+        if self.0 == 0 {
+            return None;
+        }
+        let idx = self.0.trailing_zeros() as usize;
+        // clear the lowest bit
+        self.0 &= self.0 - 1;
+        Some(idx_pos(idx))
+    }
+}
+
+pub const KNIGHT_OPTS: &[Pos] = &[
+    Pos { x: 1, y: 2 },
+    Pos { x: -1, y: 2 },
+    Pos { x: 1, y: -2 },
+    Pos { x: -1, y: -2 },
+    Pos { x: 2, y: 1 },
+    Pos { x: -2, y: 1 },
+    Pos { x: 2, y: -1 },
+    Pos { x: -2, y: -1 },
+];
+const KING_OPTS: &[Pos] = &[
+    Pos { x: 1, y: 1 },
+    Pos { x: 1, y: 0 },
+    Pos { x: 1, y: -1 },
+    Pos { x: 0, y: 1 },
+    Pos { x: 0, y: -1 },
+    Pos { x: -1, y: 1 },
+    Pos { x: -1, y: 0 },
+    Pos { x: -1, y: -1 },
+];
+const WHITE_PAWN_OPTS: &[Pos] = &[Pos { x: 1, y: 1 }, Pos { x: -1, y: 1 }];
+const BLACK_PAWN_OPTS: &[Pos] = &[Pos { x: 1, y: -1 }, Pos { x: -1, y: -1 }];
+
+// the first 4 directions walk towards higher bit indices
+const RAY_DIRS: [Pos; 8] = [
+    Pos { x: 0, y: 1 },
+    Pos { x: 1, y: 0 },
+    Pos { x: 1, y: 1 },
+    Pos { x: -1, y: 1 },
+    Pos { x: 0, y: -1 },
+    Pos { x: -1, y: 0 },
+    Pos { x: -1, y: -1 },
+    Pos { x: 1, y: -1 },
+];
+const ROOK_DIRS: [usize; 4] = [0, 1, 4, 5];
+const BISHOP_DIRS: [usize; 4] = [2, 3, 6, 7];
+
+const fn on_board(x: i8, y: i8) -> bool {
+    x >= 0 && x < BOARD_DIM.x && y >= 0 && y < BOARD_DIM.y
+}
+
+// no for loops or iterators in const fn, hence the while loops
+const fn leaper_table(opts: &[Pos]) -> [BitBoard; 64] {
+    let mut table = [0; 64];
+    let mut sq = 0;
+    while sq < 64 {
+        let (x, y) = ((sq % 8) as i8, (sq / 8) as i8);
+        let mut i = 0;
+        while i < opts.len() {
+            let (nx, ny) = (x + opts[i].x, y + opts[i].y);
+            if on_board(nx, ny) {
+                table[sq] |= 1 << (ny * 8 + nx);
+            }
+            i += 1;
+        }
+        sq += 1;
+    }
+    table
+}
+
+// every square along a direction until the edge of the board
+const fn ray_table() -> [[BitBoard; 64]; 8] {
+    let mut table = [[0; 64]; 8];
+    let mut dir = 0;
+    while dir < 8 {
+        let mut sq = 0;
+        while sq < 64 {
+            let (mut x, mut y) = ((sq % 8) as i8, (sq / 8) as i8);
+            loop {
+                x += RAY_DIRS[dir].x;
+                y += RAY_DIRS[dir].y;
+                if !on_board(x, y) {
+                    break;
+                }
+                table[dir][sq] |= 1 << (y * 8 + x);
+            }
+            sq += 1;
+        }
+        dir += 1;
+    }
+    table
+}
+
+static KNIGHT_ATTACKS: [BitBoard; 64] = leaper_table(KNIGHT_OPTS);
+static KING_ATTACKS: [BitBoard; 64] = leaper_table(KING_OPTS);
+static PAWN_ATTACKS: [[BitBoard; 64]; 2] =
+    [leaper_table(WHITE_PAWN_OPTS), leaper_table(BLACK_PAWN_OPTS)];
+static RAYS: [[BitBoard; 64]; 8] = ray_table();
+
+pub fn knight_attacks(pos: Pos) -> BitBoard {
+    KNIGHT_ATTACKS[pos_idx(pos)]
+}
+pub fn king_attacks(pos: Pos) -> BitBoard {
+    KING_ATTACKS[pos_idx(pos)]
+}
+// squares a pawn of color clr on pos attacks
+pub fn pawn_attacks(clr: Color, pos: Pos) -> BitBoard {
+    PAWN_ATTACKS[clr as usize][pos_idx(pos)]
+}
+
+// the ray is cut off after the first piece it hits (inclusive)
+fn ray_attacks(dir: usize, sq: usize, occ: BitBoard) -> BitBoard {
+    let ray = RAYS[dir][sq];
+    let blockers = ray & occ;
+    if blockers == 0 {
+        return ray;
+    }
+    let first = if dir < 4 {
+        blockers.trailing_zeros()
+    } else {
+        63 - blockers.leading_zeros()
+    };
+    ray ^ RAYS[dir][first as usize]
+}
+fn slider_attacks(dirs: &[usize], pos: Pos, occ: BitBoard) -> BitBoard {
+    let sq = pos_idx(pos);
+    dirs.iter()
+        .fold(0, |acc, &dir| acc | ray_attacks(dir, sq, occ))
+}
+pub fn bishop_attacks(pos: Pos, occ: BitBoard) -> BitBoard {
+    slider_attacks(&BISHOP_DIRS, pos, occ)
+}
+pub fn rook_attacks(pos: Pos, occ: BitBoard) -> BitBoard {
+    slider_attacks(&ROOK_DIRS, pos, occ)
+}
+pub fn queen_attacks(pos: Pos, occ: BitBoard) -> BitBoard {
+    bishop_attacks(pos, occ) | rook_attacks(pos, occ)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::string::*;
+    use alloc::vec::Vec;
+
+    fn p(s: &str) -> Pos {
+        str::parse(s).unwrap()
+    }
+
+    #[test]
+    fn leapers() {
+        assert_eq!(knight_attacks(p("a1")), pos_bit(p("b3")) | pos_bit(p("c2")));
+        assert_eq!(king_attacks(p("h8")).count_ones(), 3);
+        assert_eq!(pawn_attacks(Color::White, p("a2")), pos_bit(p("b3")));
+        assert_eq!(
+            pawn_attacks(Color::Black, p("e7")),
+            pos_bit(p("d6")) | pos_bit(p("f6"))
+        );
+    }
+    #[test]
+    fn sliders() {
+        assert_eq!(rook_attacks(p("a1"), 0).count_ones(), 14);
+        assert_eq!(queen_attacks(p("d4"), 0).count_ones(), 27);
+        // blockers are included, squares behind them are not
+        let occ = pos_bit(p("d6")) | pos_bit(p("b2"));
+        let att = bishop_attacks(p("d4"), occ) | rook_attacks(p("d4"), occ);
+        assert!(att & pos_bit(p("d6")) != 0);
+        assert!(att & pos_bit(p("d7")) == 0);
+        assert!(att & pos_bit(p("b2")) != 0);
+        assert!(att & pos_bit(p("a1")) == 0);
+    }
+    #[test]
+    fn spans() {
+        assert_eq!(rank_span(p("g1"), p("c1")), 0b0111_1100);
+        assert_eq!(rank_span(p("e8"), p("e8")), pos_bit(p("e8")));
+        assert_eq!(adjacent_files(0), file_bits(1));
+        assert_eq!((adjacent_files(4) & rank_bits(0)).count_ones(), 2);
+        assert_eq!(ranks_ahead(Color::White, 6), rank_bits(7));
+        assert_eq!(ranks_ahead(Color::White, 7), 0);
+        assert_eq!(ranks_ahead(Color::Black, 1), rank_bits(0));
+    }
+    #[test]
+    fn iter_bits() {
+        let bits: Vec<_> = BitIter(pos_bit(p("h8")) | pos_bit(p("c1")))
+            .map(|pos| pos.to_string())
+            .collect();
+        assert_eq!(bits, vec!["c1", "h8"]);
+    }
+}
@@ -1,16 +1,21 @@
 use super::*;
 
+use core::cmp;
 use core::fmt;
 use core::str;
 
 use alloc::string::*;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct StateExtra {
     castle: [[bool; 2]; 2],
     pub capture: Option<Type>,
+    // pawn that just moved 2 squares, if any
     pub enp: Option<Pos>,
+    // plies since the last capture or pawn move
+    pub ply_clock: u32,
 }
 
 impl StateExtra {
@@ -19,6 +24,7 @@ impl StateExtra {
             castle: [[false; 2]; 2],
             capture: None,
             enp: None,
+            ply_clock: 0,
         }
     }
     pub fn get_castle(&self, clr: Color, side: CastleSide) -> &bool {
@@ -27,30 +33,62 @@ impl StateExtra {
     pub fn set_castle(&mut self, clr: Color, side: CastleSide, state: bool) {
         self.castle[clr as usize][side as usize] = state;
     }
+    // zobrist contribution of castle rights and en passant
+    fn key(&self) -> u64 {
+        let mut key = zobrist::enp_key(self.enp);
+        for &clr in &[Color::White, Color::Black] {
+            for &side in &[CastleSide::Long, CastleSide::Short] {
+                if *self.get_castle(clr, side) {
+                    key ^= zobrist::castle_key(clr, side);
+                }
+            }
+        }
+        key
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct State {
     ply: u32,
-    ply_clock: u32,
     board: [[Sq; BOARD_DIM.x as usize]; BOARD_DIM.y as usize],
+    // same pieces as board, as bitboards per color and type
+    pieces: [[BitBoard; 6]; 2],
+    occupied: [BitBoard; 2],
     king_pos: [Pos; 2],
+    // zobrist hash, updated incrementally
+    key: u64,
+    // the same over just the pawns, for the pawn table
+    pawn_key: u64,
     cur_extra: StateExtra,
     extras: Vec<StateExtra>,
     moves: Vec<Move>,
+    // keys of the positions before each move, for repetitions
+    history: Vec<u64>,
     fast_eval: FastEval,
+    // None for the built-in weights, which saves allocating them
+    params: Option<Arc<EvalParams>>,
+    // starting file of each castling rook, not always a and h in chess960
+    castle_files: [[i8; 2]; 2],
+    // castling moves are written king-takes-rook, like UCI_Chess960 wants
+    chess960: bool,
 }
-// returns x value (src, dst)
-pub fn castle_rook_path(clr: Color, side: CastleSide) -> (Pos, Pos) {
-    let (src, dst) = match side {
-        CastleSide::Long => (0, 3),
-        CastleSide::Short => (7, 5),
+// the king always ends up on the c or g file, chess960 or not
+pub fn castle_king_dst(clr: Color, side: CastleSide) -> Pos {
+    let x = match side {
+        CastleSide::Long => 2,
+        CastleSide::Short => 6,
     };
-    let y = rel_y(clr, 0);
-    (Pos { x: src, y }, Pos { x: dst, y })
+    Pos {
+        x,
+        y: rel_y(clr, 0),
+    }
+}
+// the move make_null_move puts in moves()
+pub fn is_null(mv: Move) -> bool {
+    mv.a == mv.b && mv.extra.is_none()
 }
 // gets the position of the taken pawn from en passant
-fn en_passant_cap(mv: Move) -> Pos {
+pub fn en_passant_cap(mv: Move) -> Pos {
     // x/col of dest sq, y/row of src sq
     Pos {
         x: mv.b.x,
@@ -74,14 +112,32 @@ impl State {
         self.get(pos).unwrap()
     }
     fn set(&mut self, pos: Pos, x: Sq) {
-        self.fast_eval.change(false, *self.idx(pos), pos);
-        self.fast_eval.change(true, x, pos);
         let sq = self.get_mut(pos).unwrap();
+        let old = *sq;
         *sq = x;
+        let bit = pos_bit(pos);
+        if let Sq(Some(Piece { clr, typ })) = old {
+            let params = self.params.as_deref().unwrap_or(&DEFAULT_PARAMS);
+            self.fast_eval.change(false, old, pos, params);
+            self.key ^= zobrist::sq_key(old, pos);
+            if typ == Type::Pawn {
+                self.pawn_key ^= zobrist::sq_key(old, pos);
+            }
+            self.pieces[clr as usize][typ as usize] &= !bit;
+            self.occupied[clr as usize] &= !bit;
+        }
         let Piece { clr, typ } = match x {
             Sq(Some(pc)) => pc,
             Sq(None) => return,
         };
+        let params = self.params.as_deref().unwrap_or(&DEFAULT_PARAMS);
+        self.fast_eval.change(true, x, pos, params);
+        self.key ^= zobrist::sq_key(x, pos);
+        if typ == Type::Pawn {
+            self.pawn_key ^= zobrist::sq_key(x, pos);
+        }
+        self.pieces[clr as usize][typ as usize] |= bit;
+        self.occupied[clr as usize] |= bit;
         if typ == Type::King {
             self.set_king_pos(clr, pos);
         }
@@ -89,27 +145,84 @@ impl State {
 
     // every other turn, 0 starts at white.
     pub fn turn(&self) -> Color {
-        if self.ply % 2 == 0 {
+        if self.ply.is_multiple_of(2) {
             Color::White
         } else {
             Color::Black
         }
     }
     fn commit_extra(&mut self, extra: StateExtra) {
+        // most moves don't touch castling or en passant
+        if (self.cur_extra.castle, self.cur_extra.enp) != (extra.castle, extra.enp) {
+            self.key ^= self.cur_extra.key() ^ extra.key();
+        }
         self.cur_extra = extra;
     }
     pub fn get_extra(&self) -> &StateExtra {
         &self.cur_extra
     }
+    pub fn pieces(&self, clr: Color, typ: Type) -> BitBoard {
+        self.pieces[clr as usize][typ as usize]
+    }
+    pub fn occupied(&self, clr: Color) -> BitBoard {
+        self.occupied[clr as usize]
+    }
+    pub fn occupied_all(&self) -> BitBoard {
+        self.occupied[0] | self.occupied[1]
+    }
     pub fn get_king_pos(&self, clr: Color) -> &Pos {
         &self.king_pos[clr as usize]
     }
     pub fn set_king_pos(&mut self, clr: Color, pos: Pos) {
         self.king_pos[clr as usize] = pos;
     }
+    // returns rook (src, dst) for castling
+    pub fn castle_rook_path(&self, clr: Color, side: CastleSide) -> (Pos, Pos) {
+        let src = self.castle_files[clr as usize][side as usize];
+        let dst = match side {
+            CastleSide::Long => 3,
+            CastleSide::Short => 5,
+        };
+        let y = rel_y(clr, 0);
+        (Pos { x: src, y }, Pos { x: dst, y })
+    }
+    pub fn chess960(&self) -> bool {
+        self.chess960
+    }
+    // only changes how castling moves are written, the rules are the same
+    pub fn set_chess960(&mut self, chess960: bool) {
+        self.chess960 = chess960;
+    }
     pub fn move_len(&self) -> usize {
         self.moves.len()
     }
+    // moves made since the position was set up, oldest first
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+    // full move number as written in fen and pgn
+    pub fn full_move(&self) -> u32 {
+        1 + self.ply / 2
+    }
+    // zobrist hash of the position
+    pub fn key(&self) -> u64 {
+        self.key
+    }
+    // zobrist hash of just the pawns
+    pub fn pawn_key(&self) -> u64 {
+        self.pawn_key
+    }
+    // slow path for key(), used when parsing and to check incremental updates
+    pub fn compute_key(&self) -> u64 {
+        let mut key = zobrist::turn_key(self.turn()) ^ self.cur_extra.key();
+        for y in 0..BOARD_DIM.y {
+            for x in 0..BOARD_DIM.x {
+                let pos = Pos { y, x };
+                key ^= zobrist::sq_key(*self.idx(pos), pos);
+            }
+        }
+        key
+    }
 
     pub fn rel_neg(&self, score: i16) -> i16 {
         self.turn().rel_neg(score)
@@ -117,6 +230,30 @@ impl State {
     pub fn fast_score(&self) -> i16 {
         self.fast_eval.score()
     }
+    // fast_score before tapering
+    pub(crate) fn fast_pair(&self) -> (i16, i16) {
+        self.fast_eval.pair()
+    }
+    // the weights eval and fast_score use
+    pub fn eval_params(&self) -> &EvalParams {
+        self.params.as_deref().unwrap_or(&DEFAULT_PARAMS)
+    }
+    // rescores the board with params, the pawn table has to be cleared too
+    pub fn set_eval_params(&mut self, params: Arc<EvalParams>) {
+        let mut fast_eval = FastEval::default();
+        for y in 0..BOARD_DIM.y {
+            for x in 0..BOARD_DIM.x {
+                let pos = Pos { x, y };
+                fast_eval.change(true, *self.idx(pos), pos, &params);
+            }
+        }
+        self.fast_eval = fast_eval;
+        self.params = Some(params);
+    }
+    // how far from the endgame the material is, see FastEval::phase
+    pub fn phase(&self) -> i32 {
+        self.fast_eval.phase()
+    }
     pub fn slow_score(&mut self) -> i16 {
         let moves = self.gen_sudo_moves();
         for mv in moves {
@@ -127,6 +264,33 @@ impl State {
         // we have no legal moves.
         self.end_score()
     }
+    // how many times this position came up before. only looks back to the
+    // last capture or pawn move, since those can't be undone
+    pub fn repetitions(&self) -> usize {
+        self.repetitions_within(self.get_extra().ply_clock as usize)
+    }
+    // repetitions within the last n plies
+    pub fn repetitions_within(&self, plies: usize) -> usize {
+        let plies = cmp::min(plies, self.get_extra().ply_clock as usize);
+        self.history
+            .iter()
+            .rev()
+            .take(plies)
+            // only positions with the same side to move can match
+            .skip(1)
+            .step_by(2)
+            .filter(|&&key| key == self.key)
+            .count()
+    }
+    pub fn is_threefold(&self) -> bool {
+        self.repetitions() >= 2
+    }
+    pub fn is_fifty_move(&self) -> bool {
+        self.get_extra().ply_clock >= 100
+    }
+    pub fn is_draw(&self) -> bool {
+        self.is_threefold() || self.is_fifty_move()
+    }
     pub fn end_score(&self) -> i16 {
         if self.in_check(self.turn()) {
             self.rel_neg(-CHECKMATE)
@@ -143,6 +307,7 @@ impl State {
         // copy extra data and push
         self.extras.push(self.cur_extra);
         self.moves.push(mv);
+        self.history.push(self.key);
 
         // moving from a to b
         let mut a_pc = self.idx(mv.a).0.unwrap();
@@ -151,12 +316,16 @@ impl State {
         // ensure we are allowed to move the piece
         debug_assert!(a_pc.clr == self.turn());
 
-        // ensure we are not bumping into our own piece
+        // ensure we are not bumping into our own piece, unless it's a
+        // chess960 castle onto the rook
         debug_assert!(match b_sq {
-            Sq(Some(pc)) => pc.clr != a_pc.clr,
+            Sq(Some(pc)) => pc.clr != a_pc.clr || matches!(mv.extra, Some(MvExtra::Castle(_))),
             Sq(None) => true,
         });
 
+        // in chess960 the king or rook can land where the other one started,
+        // so take both off the board before putting them back
+        let mut castle_rook = None;
         match mv.extra {
             Some(MvExtra::EnPassant) => {
                 debug_assert!(a_pc.typ == Type::Pawn && b_sq == Sq(None));
@@ -165,21 +334,24 @@ impl State {
             }
             Some(MvExtra::Promote(typ)) => a_pc.typ = typ,
             Some(MvExtra::Castle(side)) => {
-                let (src, dst) = castle_rook_path(self.turn(), side);
+                let (src, dst) = self.castle_rook_path(self.turn(), side);
                 self.set(src, Sq(None));
-                self.set(dst, Sq::new(self.turn(), Type::Rook));
+                mv.b = castle_king_dst(self.turn(), side);
+                castle_rook = Some(dst);
             }
             None => (),
         }
         let mut st_extra = self.cur_extra;
         st_extra.enp = None;
+        st_extra.ply_clock = if a_pc.typ == Type::Pawn || mv.capture.is_some() {
+            0
+        } else {
+            st_extra.ply_clock + 1
+        };
         match a_pc.typ {
-            Type::Pawn =>
             // prep for en passant next move
-            {
-                if (mv.b.y - mv.a.y).abs() == 2 {
-                    st_extra.enp = Some(mv.b);
-                }
+            Type::Pawn if (mv.b.y - mv.a.y).abs() == 2 => {
+                st_extra.enp = Some(mv.b);
             }
             Type::King => {
                 st_extra.set_castle(self.turn(), CastleSide::Long, false);
@@ -188,7 +360,7 @@ impl State {
             Type::Rook => {
                 for &side in &[CastleSide::Long, CastleSide::Short] {
                     // if the rook is moving away from its original position
-                    if mv.a == castle_rook_path(self.turn(), side).0 {
+                    if mv.a == self.castle_rook_path(self.turn(), side).0 {
                         st_extra.set_castle(self.turn(), side, false)
                     }
                 }
@@ -199,7 +371,7 @@ impl State {
         if mv.capture == Some(Type::Rook) {
             let enemy = self.turn().other();
             for &side in &[CastleSide::Long, CastleSide::Short] {
-                if mv.b == castle_rook_path(enemy, side).0 {
+                if mv.b == self.castle_rook_path(enemy, side).0 {
                     st_extra.set_castle(enemy, side, false);
                 }
             }
@@ -210,21 +382,36 @@ impl State {
         // move the pieces
         self.set(mv.a, Sq(None));
         self.set(mv.b, Sq(Some(a_pc)));
+        if let Some(dst) = castle_rook {
+            self.set(dst, Sq::new(self.turn(), Type::Rook));
+        }
 
         // don't change self.turn() till the end
         self.ply += 1;
+        self.key ^= zobrist::turn_key(Color::Black);
     }
     pub fn unmake_move(&mut self) {
         self.ply -= 1;
+        self.key ^= zobrist::turn_key(Color::Black);
         let st_extra = self.extras.pop().unwrap();
+        self.history.pop();
 
         let mut mv = self.moves.pop().unwrap();
 
+        // like make_move, the rook comes off first and goes back last
+        let mut castle_rook = None;
+        if let Some(MvExtra::Castle(side)) = mv.extra {
+            let (src, dst) = self.castle_rook_path(self.turn(), side);
+            self.set(dst, Sq(None));
+            mv.b = castle_king_dst(self.turn(), side);
+            castle_rook = Some(src);
+        }
+
         // moving from b to a
         let mut b_pc = self.idx(mv.b).0.unwrap();
 
-        // we came from a, it should be empty
-        debug_assert!(*self.idx(mv.a) == Sq(None));
+        // we came from a, it should be empty (unless the king stayed put)
+        debug_assert!(*self.idx(mv.a) == Sq(None) || mv.a == mv.b);
 
         let enemy_turn = self.turn().other();
         let enemy_sq = |typ| Sq::new(enemy_turn, typ);
@@ -237,18 +424,12 @@ impl State {
                 self.set(en_passant_cap(mv), enemy_sq(Type::Pawn));
             }
             Some(MvExtra::Promote(_)) => b_pc.typ = Type::Pawn,
-            Some(MvExtra::Castle(side)) => {
-                let (src, dst) = castle_rook_path(self.turn(), side);
-                self.set(dst, Sq(None));
-                self.set(src, Sq::new(self.turn(), Type::Rook));
-            }
-            None => (),
+            Some(MvExtra::Castle(_)) | None => (),
         }
 
         self.commit_extra(st_extra);
 
         // move the pieces, restoring a capture
-        self.set(mv.a, Sq(Some(b_pc)));
         self.set(
             mv.b,
             match mv.capture {
@@ -256,17 +437,62 @@ impl State {
                 None => Sq(None),
             },
         );
+        self.set(mv.a, Sq(Some(b_pc)));
+        if let Some(src) = castle_rook {
+            self.set(src, Sq::new(self.turn(), Type::Rook));
+        }
+    }
+    // passes the turn, for null move pruning. moves() gets the king standing
+    // still in its place
+    pub fn make_null_move(&mut self) {
+        debug_assert!(!self.in_check(self.turn()));
+        let king = *self.get_king_pos(self.turn());
+        self.extras.push(self.cur_extra);
+        self.moves.push(Move {
+            a: king,
+            b: king,
+            capture: None,
+            extra: None,
+        });
+        self.history.push(self.key);
+
+        let mut st_extra = self.cur_extra;
+        // the chance to take en passant is gone after any move
+        st_extra.enp = None;
+        // the clock restarts, so neither repetition nor the fifty move rule
+        // looks back past the pass. the game never reached what follows, so
+        // matching an earlier position isn't a real draw, and a null move
+        // subtree is far too shallow to use up fifty moves
+        st_extra.ply_clock = 0;
+        self.commit_extra(st_extra);
+        self.ply += 1;
+        self.key ^= zobrist::turn_key(Color::Black);
+    }
+    pub fn unmake_null_move(&mut self) {
+        self.ply -= 1;
+        self.key ^= zobrist::turn_key(Color::Black);
+        let st_extra = self.extras.pop().unwrap();
+        self.history.pop();
+        self.moves.pop();
+        self.commit_extra(st_extra);
     }
     pub fn zero_board() -> Self {
         State {
             ply: 0,
-            ply_clock: 0,
             board: [[Sq(None); BOARD_DIM.x as usize]; BOARD_DIM.y as usize],
+            pieces: [[0; 6]; 2],
+            occupied: [0; 2],
             king_pos: [Pos { x: 0, y: 0 }, Pos { x: 0, y: 0 }],
+            key: 0,
+            pawn_key: 0,
             cur_extra: StateExtra::zero_init(),
             extras: vec![],
             moves: vec![],
+            history: vec![],
             fast_eval: Default::default(),
+            params: None,
+            castle_files: [[0, BOARD_DIM.x - 1]; 2],
+            chess960: false,
         }
     }
 }
@@ -294,7 +520,7 @@ impl fmt::Display for State {
                 let mut count: u8 = 0;
                 let mut s = String::new();
                 let dump = |count: &mut u8| {
-                    let d = ('0' as u8 + *count) as char;
+                    let d = (b'0' + *count) as char;
                     *count = 0;
                     if d == '0' {
                         String::new()
@@ -316,26 +542,39 @@ impl fmt::Display for State {
             "/",
             self.board.iter().rev(),
         );
-        let move_num = 1 + self.ply / 2;
+        let move_num = self.full_move();
         let mut castle_rights = String::with_capacity(4);
         for clr in [Color::White, Color::Black] {
             for (typ, side) in [
                 (Type::King, CastleSide::Short),
                 (Type::Queen, CastleSide::Long),
             ] {
-                if *self.get_extra().get_castle(clr, side) {
-                    let pc = format!("{}", Sq::new(clr, typ));
-                    castle_rights += &pc;
+                if !*self.get_extra().get_castle(clr, side) {
+                    continue;
+                }
+                // X-FEN: KQkq when it's the outermost rook, otherwise the file
+                let rook = self.castle_rook_path(clr, side).0;
+                let mut pc = match self.outer_rook(clr, side) {
+                    Some(outer) if outer == rook => Sq::new(clr, typ).to_string(),
+                    _ => rook.to_string()[..1].to_string(),
+                };
+                if clr == Color::White {
+                    pc.make_ascii_uppercase();
                 }
+                castle_rights += &pc;
             }
         }
         if castle_rights.is_empty() {
             castle_rights = "-".to_string();
         }
-        let enp = self
-            .get_extra()
-            .enp
-            .map_or("-".to_string(), |e| e.to_string());
+        // we keep the pawn's square, FEN wants the one it skipped
+        let enp = self.get_extra().enp.map_or("-".to_string(), |e| {
+            let skipped = Pos {
+                x: e.x,
+                y: rel_y(self.turn().other(), 2),
+            };
+            skipped.to_string()
+        });
 
         write!(
             f,
@@ -344,7 +583,7 @@ impl fmt::Display for State {
             self.turn(),
             castle_rights,
             enp,
-            self.ply_clock,
+            self.get_extra().ply_clock,
             move_num
         )
     }
@@ -354,72 +593,178 @@ impl str::FromStr for State {
     type Err = ChessParseError;
 
     fn from_str(fen: &str) -> Result<Self, Self::Err> {
-        // seems like errors are a pain in rust, make the pain go away :)
-        fn conv_err<T, E>(res: Result<T, E>) -> Result<T, ChessParseError> {
-            match res.ok() {
-                Some(x) => Ok(x),
-                None => Err(ChessParseError::new("FEN")),
-            }
-        }
-        let p_sq = |c: char| conv_err(str::parse::<Sq>(&c.to_string()));
+        use ChessParseError as E;
 
         let mut state = State::zero_board();
-        let items = fen.split(" ").collect::<Vec<_>>();
-        if let [board, turn, castle, enp, half, full] = items.as_slice() {
-            let clr_add = match *turn {
-                "w" => 0,
-                "b" => 1,
-                _ => return Err(ChessParseError::new("FEN")),
-            };
-            let full_u = conv_err(str::parse::<u32>(full))?;
-            // full turns are double, we start at ply 0, not full turn 1
-            state.ply = 2 * (full_u - 1) + clr_add;
-            state.ply_clock = conv_err(str::parse::<u32>(half))?;
-
-            let mut extra = StateExtra::zero_init();
-            extra.enp = str::parse::<Pos>(enp).ok();
-            for c in castle.chars() {
-                let (clr, side) = match c {
-                    'q' => (Color::Black, CastleSide::Long),
-                    'k' => (Color::Black, CastleSide::Short),
-                    'Q' => (Color::White, CastleSide::Long),
-                    'K' => (Color::White, CastleSide::Short),
-                    '-' => continue,
-                    _ => return Err(ChessParseError::new("FEN")),
-                };
-                extra.set_castle(clr, side, true);
+        let items = fen.split_ascii_whitespace().collect::<Vec<_>>();
+        let [board, turn, castle, enp, half, full] = match items.as_slice() {
+            &[a, b, c, d, e, f] => [a, b, c, d, e, f],
+            _ => return Err(E::FieldCount(items.len())),
+        };
+
+        let clr_add = match turn {
+            "w" => 0,
+            "b" => 1,
+            _ => return Err(E::Turn(turn.to_string())),
+        };
+        // full turns are double, we start at ply 0, not full turn 1
+        state.ply = match str::parse::<u32>(full) {
+            Ok(n) if n >= 1 => (n - 1).checked_mul(2).and_then(|p| p.checked_add(clr_add)),
+            _ => None,
+        }
+        .ok_or_else(|| E::FullMove(full.to_string()))?;
+
+        let mut extra = StateExtra::zero_init();
+        extra.ply_clock = str::parse::<u32>(half).map_err(|_| E::HalfMove(half.to_string()))?;
+        if enp != "-" {
+            let e = str::parse::<Pos>(enp).map_err(|_| E::EnPassant(enp.to_string()))?;
+            // FEN has the skipped square, we keep the pawn that skipped it
+            if e.y != rel_y(state.turn().other(), 2) {
+                return Err(E::EnPassant(enp.to_string()));
             }
-            state.commit_extra(extra);
+            extra.enp = Some(Pos {
+                x: e.x,
+                y: rel_y(state.turn().other(), 3),
+            });
+        }
 
-            for (y, row) in board.rsplit("/").enumerate() {
-                if y >= BOARD_DIM.y as usize {
-                    return Err(ChessParseError::new("FEN"));
+        let rows: Vec<_> = board.rsplit('/').collect();
+        if rows.len() != BOARD_DIM.y as usize {
+            return Err(E::RankCount(rows.len()));
+        }
+        for (y, row) in rows.into_iter().enumerate() {
+            let y = y as i8;
+            let mut x: i8 = 0;
+            for c in row.chars() {
+                if x >= BOARD_DIM.x {
+                    return Err(E::RankLength(y + 1));
                 }
-                let mut x: usize = 0;
-                for c in row.chars() {
-                    if "123456789".contains(c) {
-                        x += c as usize - '0' as usize;
-                    } else {
-                        if x >= BOARD_DIM.x as usize {
-                            return Err(ChessParseError::new("FEN"));
-                        }
-                        state.set(
-                            Pos {
-                                x: x as i8,
-                                y: y as i8,
-                            },
-                            p_sq(c)?,
-                        );
-                        x += 1;
+                if c.is_ascii_digit() {
+                    let skip = c as i8 - '0' as i8;
+                    if skip == 0 {
+                        return Err(E::RankLength(y + 1));
                     }
+                    x += skip;
+                } else {
+                    state.set(Pos { x, y }, str::parse::<Sq>(&c.to_string())?);
+                    x += 1;
+                }
+            }
+            if x != BOARD_DIM.x {
+                return Err(E::RankLength(y + 1));
+            }
+        }
+
+        // KQkq means the outermost rook (X-FEN), a file letter picks the
+        // rook on that file (Shredder-FEN)
+        if castle.is_empty() || (castle.contains('-') && castle != "-") {
+            return Err(E::Castle(castle.to_string()));
+        }
+        for c in castle.chars().filter(|&c| c != '-') {
+            let clr = match c.is_ascii_uppercase() {
+                true => Color::White,
+                false => Color::Black,
+            };
+            let king = *state.get_king_pos(clr);
+            let (side, file) = match c.to_ascii_lowercase() {
+                'q' => (CastleSide::Long, state.outer_rook(clr, CastleSide::Long)),
+                'k' => (CastleSide::Short, state.outer_rook(clr, CastleSide::Short)),
+                f @ 'a'..='h' => {
+                    let x = f as i8 - 'a' as i8;
+                    let side = match x < king.x {
+                        true => CastleSide::Long,
+                        false => CastleSide::Short,
+                    };
+                    (
+                        side,
+                        Some(Pos {
+                            x,
+                            y: rel_y(clr, 0),
+                        }),
+                    )
                 }
+                _ => return Err(E::Castle(castle.to_string())),
+            };
+            // a missing rook is caught by validate
+            if let Some(rook) = file {
+                state.castle_files[clr as usize][side as usize] = rook.x;
             }
+            extra.set_castle(clr, side, true);
+        }
+        state.commit_extra(extra);
+        state.key = state.compute_key();
+        state.validate()?;
 
-            Ok(state)
-        } else {
-            Err(ChessParseError::new("FEN"))
+        Ok(state)
+    }
+}
+
+impl State {
+    // the rook furthest from the king on one side of the back rank
+    fn outer_rook(&self, clr: Color, side: CastleSide) -> Option<Pos> {
+        let king = *self.get_king_pos(clr);
+        let mut rooks = BitIter(self.pieces(clr, Type::Rook) & rank_bits(rel_y(clr, 0)))
+            .filter(|rook| rook.y == king.y);
+        match side {
+            CastleSide::Long => rooks.find(|rook| rook.x < king.x),
+            CastleSide::Short => rooks.filter(|rook| rook.x > king.x).last(),
         }
     }
+
+    // checks that the position could come up in a game, past what the FEN
+    // syntax itself can express
+    pub fn validate(&self) -> Result<(), ChessParseError> {
+        use ChessParseError as E;
+
+        for &clr in &[Color::White, Color::Black] {
+            let kings = self.pieces(clr, Type::King).count_ones();
+            if kings != 1 {
+                return Err(E::KingCount(clr, kings));
+            }
+        }
+        let back_ranks = rank_bits(0) | rank_bits(BOARD_DIM.y - 1);
+        let pawns = self.pieces(Color::White, Type::Pawn) | self.pieces(Color::Black, Type::Pawn);
+        if let Some(pos) = BitIter(pawns & back_ranks).next() {
+            return Err(E::PawnOnBackRank(pos));
+        }
+        if self.in_check(self.turn().other()) {
+            return Err(E::OpponentInCheck);
+        }
+        for &clr in &[Color::White, Color::Black] {
+            for &side in &[CastleSide::Long, CastleSide::Short] {
+                // the king can start on any file, but the rook must be on
+                // the side it castles to
+                let king = *self.get_king_pos(clr);
+                let rook = self.castle_rook_path(clr, side).0;
+                let rook_side = match side {
+                    CastleSide::Long => rook.x < king.x,
+                    CastleSide::Short => rook.x > king.x,
+                };
+                let in_place = king.y == rel_y(clr, 0)
+                    && rook_side
+                    && *self.idx(rook) == Sq::new(clr, Type::Rook);
+                if *self.get_extra().get_castle(clr, side) && !in_place {
+                    return Err(E::CastleRights(clr, side));
+                }
+            }
+        }
+        if let Some(pawn) = self.get_extra().enp {
+            // the pawn must be there, with the squares it moved over empty
+            let enemy = self.turn().other();
+            let dir = Pos {
+                x: 0,
+                y: rel_y(enemy, 1) - rel_y(enemy, 0),
+            };
+            let skipped = pawn - dir;
+            if *self.idx(pawn) != Sq::new(enemy, Type::Pawn)
+                || *self.idx(skipped) != Sq(None)
+                || *self.idx(skipped - dir) != Sq(None)
+            {
+                return Err(E::EnPassant(skipped.to_string()));
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Default for State {
@@ -453,4 +798,190 @@ R N B Q K B N R"
             assert_eq!(fen, str::parse::<State>(fen).unwrap().to_string());
         }
     }
+    #[test]
+    fn fen_enp() {
+        let mut state = State::default();
+        state.run_moves("e2e4".split(' '));
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+        assert_eq!(state.to_string(), fen);
+        assert_eq!(str::parse::<State>(fen).unwrap().key(), state.key());
+    }
+    #[test]
+    fn fen_chess960() {
+        let fen = |s: &str| str::parse::<State>(s).unwrap().to_string();
+        // Shredder-FEN becomes KQkq when the rooks are the outer ones
+        assert_eq!(
+            fen("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9"),
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9"
+        );
+        assert_eq!(
+            fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1"),
+            State::default().to_string()
+        );
+        // an inner rook needs its file spelled out
+        let inner = "4k3/8/8/8/8/8/8/RR2K3 w B - 0 1";
+        assert_eq!(fen(inner), inner);
+        let state: State = str::parse(inner).unwrap();
+        assert_eq!(
+            state.castle_rook_path(Color::White, CastleSide::Long).0,
+            str::parse("b1").unwrap()
+        );
+        assert_eq!(
+            str::parse::<State>("4k3/8/8/8/8/8/8/RR2K3 w Q - 0 1")
+                .unwrap()
+                .castle_rook_path(Color::White, CastleSide::Long)
+                .0,
+            str::parse("a1").unwrap()
+        );
+        assert_eq!(
+            str::parse::<State>("4k3/8/8/8/8/8/8/RR2K3 w C - 0 1").err(),
+            Some(ChessParseError::CastleRights(
+                Color::White,
+                CastleSide::Long
+            ))
+        );
+    }
+    #[test]
+    fn fen_invalid() {
+        use ChessParseError as E;
+        let err = |fen: &str| str::parse::<State>(fen).err().unwrap();
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR";
+        assert_eq!(err(start), E::FieldCount(1));
+        assert_eq!(
+            err("rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            E::RankCount(7)
+        );
+        assert_eq!(
+            err("rnbqkbnr/ppppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            E::RankLength(7)
+        );
+        assert_eq!(
+            err("rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            E::RankLength(6)
+        );
+        assert_eq!(
+            err("rnbqkbnr/pppppppp/7/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            E::RankLength(6)
+        );
+        assert_eq!(
+            err("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNx w KQkq - 0 1"),
+            E::Piece("x".to_string())
+        );
+        let fields = |rest| err(&format!("{} {}", start, rest));
+        assert_eq!(fields("x KQkq - 0 1"), E::Turn("x".to_string()));
+        assert_eq!(fields("w KQkx - 0 1"), E::Castle("KQkx".to_string()));
+        assert_eq!(fields("w K- - 0 1"), E::Castle("K-".to_string()));
+        assert_eq!(fields("w KQkq z9 0 1"), E::EnPassant("z9".to_string()));
+        assert_eq!(fields("w KQkq e3 0 1"), E::EnPassant("e3".to_string()));
+        assert_eq!(fields("w KQkq e6 0 1"), E::EnPassant("e6".to_string()));
+        assert_eq!(fields("w KQkq é 0 1"), E::EnPassant("é".to_string()));
+        assert_eq!(fields("w KQkq - x 1"), E::HalfMove("x".to_string()));
+        assert_eq!(fields("w KQkq - 0 0"), E::FullMove("0".to_string()));
+        // too many moves for the ply count
+        assert_eq!(
+            fields("w KQkq - 0 4294967295"),
+            E::FullMove("4294967295".to_string())
+        );
+
+        assert_eq!(
+            err("rnbq1bnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQ - 0 1"),
+            E::KingCount(Color::Black, 0)
+        );
+        assert_eq!(
+            err("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNP w kq - 0 1"),
+            E::PawnOnBackRank(str::parse("h1").unwrap())
+        );
+        assert_eq!(
+            err("rnbqkbnr/pppp1ppp/8/8/8/8/PPPPQPPP/RNB1KBNR w KQkq - 0 1"),
+            E::OpponentInCheck
+        );
+        assert_eq!(
+            err("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN1 w KQkq - 0 1"),
+            E::CastleRights(Color::White, CastleSide::Short)
+        );
+    }
+    #[test]
+    fn ply_clock() {
+        let mut state = State::default();
+        state.run_moves("g1f3 g8f6 f3g1".split(' '));
+        assert_eq!(state.get_extra().ply_clock, 3);
+        state.run_moves("e7e5".split(' '));
+        assert_eq!(state.get_extra().ply_clock, 0);
+        state.unmake_move();
+        assert_eq!(
+            state.to_string(),
+            "rnbqkb1r/pppppppp/5n2/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 3 2"
+        );
+    }
+    #[test]
+    fn threefold() {
+        let mut state = State::default();
+        let shuffle = "g1f3 g8f6 f3g1 f6g8".split(' ');
+        state.run_moves(shuffle.clone());
+        assert_eq!(state.repetitions(), 1);
+        assert!(!state.is_draw());
+        state.run_moves(shuffle);
+        assert_eq!(state.repetitions(), 2);
+        assert!(state.is_threefold());
+        state.unmake_move();
+        assert!(!state.is_draw());
+    }
+    #[test]
+    fn fifty_move() {
+        let mut state: State = str::parse("8/8/8/8/8/2k5/8/K6q w - - 99 80").unwrap();
+        assert!(!state.is_draw());
+        state.run_moves("a1a2".split(' '));
+        assert!(state.is_fifty_move());
+        state.run_moves("h1h2".split(' '));
+        assert!(state.is_draw());
+    }
+    #[test]
+    fn null_move() {
+        let fen = "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2";
+        let mut state: State = str::parse(fen).unwrap();
+        state.run_moves("f7f5".split(' '));
+        let before = state.clone();
+        state.make_null_move();
+        assert_eq!(state.turn(), Color::Black);
+        assert_eq!(state.get_extra().enp, None);
+        assert_eq!(state.key(), state.compute_key());
+        assert_ne!(state.key(), before.key());
+        assert!(is_null(*state.moves().last().unwrap()));
+        state.run_moves("e7e6".split(' '));
+        assert!(state.find_move("e5f6").is_none());
+        state.unmake_move();
+        state.unmake_null_move();
+        assert_eq!(state, before);
+        assert!(state.find_move("e5f6").is_some());
+    }
+    #[test]
+    fn null_move_clock() {
+        let mut state = State::default();
+        state.run_moves("g1f3 g8f6 f3g1 f6g8".split(' '));
+        assert_eq!(state.repetitions(), 1);
+        state.make_null_move();
+        assert_eq!(state.get_extra().ply_clock, 0);
+        state.unmake_null_move();
+        assert_eq!(state.get_extra().ply_clock, 4);
+        assert_eq!(state.repetitions(), 1);
+    }
+    #[test]
+    fn key_transposition() {
+        // same position through different move orders
+        let mut a = State::default();
+        a.run_moves("g1f3 g8f6 b1c3 b8c6".split(' '));
+        let mut b = State::default();
+        b.run_moves("b1c3 b8c6 g1f3 g8f6".split(' '));
+        assert_eq!(a.key(), b.key());
+        assert_eq!(a.key(), str::parse::<State>(&a.to_string()).unwrap().key());
+        // castle rights and en passant are part of the key
+        let mut c = State::default();
+        c.run_moves("g1f3 g8f6 h1g1 h8g8 g1h1 g8h8".split(' '));
+        assert_ne!(State::default().key(), c.key());
+        let mut d = State::default();
+        d.run_moves("e2e4".split(' '));
+        let e: State =
+            str::parse("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1").unwrap();
+        assert_ne!(d.key(), e.key());
+    }
 }
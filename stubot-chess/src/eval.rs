@@ -1,9 +1,11 @@
 use super::*;
 
-type ScoreTable = [[i8; BOARD_DIM.x as usize]; BOARD_DIM.y as usize];
+use core::cmp;
+
+pub(crate) type ScoreTable = [[i16; BOARD_DIM.x as usize]; BOARD_DIM.y as usize];
 
 // thank you cargo fmt for making these readable
-const PAWN_TBL: ScoreTable = [
+pub(crate) const PAWN_TBL: ScoreTable = [
     [0, 0, 0, 0, 0, 0, 0, 0],
     [5, 10, 10, -20, -20, 10, 10, 5],
     [5, -5, -10, 0, 0, -10, -5, 5],
@@ -14,7 +16,7 @@ const PAWN_TBL: ScoreTable = [
     [0, 0, 0, 0, 0, 0, 0, 0],
 ];
 
-const KNIGHT_TBL: ScoreTable = [
+pub(crate) const KNIGHT_TBL: ScoreTable = [
     [-50, -40, -30, -30, -30, -30, -40, -50],
     [-40, -20, 0, 0, 0, 0, -20, -40],
     [-30, 0, 10, 15, 15, 10, 0, -30],
@@ -25,7 +27,7 @@ const KNIGHT_TBL: ScoreTable = [
     [-50, -40, -30, -30, -30, -30, -40, -50],
 ];
 
-const BISHOP_TBL: ScoreTable = [
+pub(crate) const BISHOP_TBL: ScoreTable = [
     [-20, -10, -10, -10, -10, -10, -10, -20],
     [-10, 5, 0, 0, 0, 0, 5, -10],
     [-10, 10, 10, 10, 10, 10, 10, -10],
@@ -36,7 +38,7 @@ const BISHOP_TBL: ScoreTable = [
     [-20, -10, -10, -10, -10, -10, -10, -20],
 ];
 
-const ROOK_TBL: ScoreTable = [
+pub(crate) const ROOK_TBL: ScoreTable = [
     [0, 0, 0, 5, 5, 0, 0, 0],
     [-5, 0, 0, 0, 0, 0, 0, -5],
     [-5, 0, 0, 0, 0, 0, 0, -5],
@@ -47,7 +49,7 @@ const ROOK_TBL: ScoreTable = [
     [0, 0, 0, 0, 0, 0, 0, 0],
 ];
 
-const QUEEN_TBL: ScoreTable = [
+pub(crate) const QUEEN_TBL: ScoreTable = [
     [-20, -10, -10, -5, -5, -10, -10, -20],
     [-10, 0, 0, 0, 0, 0, 0, -10],
     [-10, 5, 5, 5, 5, 5, 0, -10],
@@ -58,7 +60,7 @@ const QUEEN_TBL: ScoreTable = [
     [-20, -10, -10, -5, -5, -10, -10, -20],
 ];
 
-const KING_TBL: ScoreTable = [
+pub(crate) const KING_TBL: ScoreTable = [
     [20, 30, 10, 0, 0, 10, 30, 20],
     [20, 20, 0, 0, 0, 0, 20, 20],
     [-10, -20, -20, -20, -20, -20, -20, -10],
@@ -69,76 +71,405 @@ const KING_TBL: ScoreTable = [
     [-30, -40, -40, -50, -50, -40, -40, -30],
 ];
 
+// the late tables are for endgames, where the king has to come out and
+// pawns are worth more the closer they get to promoting
+pub(crate) const PAWN_LATE_TBL: ScoreTable = [
+    [0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0],
+    [5, 5, 5, 5, 5, 5, 5, 5],
+    [10, 10, 10, 10, 10, 10, 10, 10],
+    [20, 20, 20, 20, 20, 20, 20, 20],
+    [35, 35, 35, 35, 35, 35, 35, 35],
+    [60, 60, 60, 60, 60, 60, 60, 60],
+    [0, 0, 0, 0, 0, 0, 0, 0],
+];
+
+pub(crate) const KNIGHT_LATE_TBL: ScoreTable = [
+    [-40, -30, -20, -20, -20, -20, -30, -40],
+    [-30, -15, -5, 0, 0, -5, -15, -30],
+    [-20, -5, 5, 10, 10, 5, -5, -20],
+    [-20, 0, 10, 15, 15, 10, 0, -20],
+    [-20, 0, 10, 15, 15, 10, 0, -20],
+    [-20, -5, 5, 10, 10, 5, -5, -20],
+    [-30, -15, -5, 0, 0, -5, -15, -30],
+    [-40, -30, -20, -20, -20, -20, -30, -40],
+];
+
+pub(crate) const BISHOP_LATE_TBL: ScoreTable = [
+    [-15, -10, -10, -5, -5, -10, -10, -15],
+    [-10, 0, 0, 0, 0, 0, 0, -10],
+    [-10, 0, 5, 5, 5, 5, 0, -10],
+    [-5, 0, 5, 10, 10, 5, 0, -5],
+    [-5, 0, 5, 10, 10, 5, 0, -5],
+    [-10, 0, 5, 5, 5, 5, 0, -10],
+    [-10, 0, 0, 0, 0, 0, 0, -10],
+    [-15, -10, -10, -5, -5, -10, -10, -15],
+];
+
+pub(crate) const ROOK_LATE_TBL: ScoreTable = [
+    [0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0],
+    [5, 5, 5, 5, 5, 5, 5, 5],
+    [0, 0, 0, 0, 0, 0, 0, 0],
+];
+
+pub(crate) const QUEEN_LATE_TBL: ScoreTable = [
+    [-20, -10, -10, -5, -5, -10, -10, -20],
+    [-10, 0, 5, 5, 5, 5, 0, -10],
+    [-10, 5, 10, 10, 10, 10, 5, -10],
+    [-5, 5, 10, 15, 15, 10, 5, -5],
+    [-5, 5, 10, 15, 15, 10, 5, -5],
+    [-10, 5, 10, 10, 10, 10, 5, -10],
+    [-10, 0, 5, 5, 5, 5, 0, -10],
+    [-20, -10, -10, -5, -5, -10, -10, -20],
+];
+
+pub(crate) const KING_LATE_TBL: ScoreTable = [
+    [-50, -30, -30, -30, -30, -30, -30, -50],
+    [-30, -30, 0, 0, 0, 0, -30, -30],
+    [-30, -10, 20, 30, 30, 20, -10, -30],
+    [-30, -10, 30, 40, 40, 30, -10, -30],
+    [-30, -10, 30, 40, 40, 30, -10, -30],
+    [-30, -10, 20, 30, 30, 20, -10, -30],
+    [-30, -20, -10, 0, 0, -10, -20, -30],
+    [-50, -40, -30, -20, -20, -30, -40, -50],
+];
+
+// how much each non-king type counts towards the middlegame. with all the
+// pieces on the board the phase is MAX_PHASE, with only kings and pawns 0
+const PHASE_WEIGHTS: [i32; 5] = [0, 1, 1, 2, 4];
+pub(crate) const MAX_PHASE: i32 = 24;
+
+// per reachable square past what a piece usually has, (middlegame,
+// endgame) for knights, bishops, rooks and queens
+pub(crate) const MOBILITY: [(i16, i16); 4] = [(4, 4), (4, 5), (2, 4), (1, 2)];
+pub(crate) const MOBILITY_BASE: [i16; 4] = [4, 6, 6, 12];
+// attack units per king zone square hit, by the same types. the penalty
+// grows with the square of the units once two pieces join in
+pub(crate) const KING_ATTACK_UNITS: [i16; 4] = [2, 2, 3, 5];
+pub(crate) const KING_DANGER_MAX: i16 = 400;
+// own pawns one and two squares in front of the king
+pub(crate) const SHIELD: [i16; 3] = [0, 15, 8];
+// enemy pawns this many squares in front of the king
+pub(crate) const STORM: [i16; 4] = [0, -10, -20, -10];
+// files at or next to the king without our pawns, or without any
+pub(crate) const KING_SEMI_OPEN: i16 = -15;
+pub(crate) const KING_OPEN: i16 = -25;
+pub(crate) const ROOK_SEMI_OPEN: (i16, i16) = (12, 5);
+pub(crate) const ROOK_OPEN: (i16, i16) = (25, 10);
+pub(crate) const BISHOP_PAIR: (i16, i16) = (30, 50);
+
 // asssumption: [CHECKMATE - 255..=CHECKMATE] are mate-in-x-ply
 pub const CHECKMATE: i16 = 20000;
 pub const MATE_BOUND: i16 = CHECKMATE - u8::MAX as i16;
 pub const DRAW: i16 = 0;
+// evals are capped well short of the mate scores, whatever the params
+pub(crate) const EVAL_MAX: i16 = MATE_BOUND / 2;
 pub fn mate_ply(ply: u8) -> i16 {
     CHECKMATE - ply as i16
 }
-const TYP_VALS: &[i16] = &[100, 320, 330, 500, 900, 0];
+// see sticks to these, the eval goes through EvalParams
+pub(crate) const TYP_VALS: [i16; 6] = [100, 320, 330, 500, 900, 0];
+
+pub(crate) fn tbl_value(tbl: &ScoreTable, pc: Piece, pos: Pos) -> i16 {
+    tbl[rel_y(pc.clr, pos.y) as usize][pos.x as usize]
+}
+
+// blends a middlegame and an endgame score by phase, see FastEval::phase
+pub(crate) fn taper(early: i16, late: i16, phase: i32) -> i16 {
+    let blended = early as i32 * phase + late as i32 * (MAX_PHASE - phase);
+    let max = EVAL_MAX as i32;
+    (blended / MAX_PHASE).clamp(-max, max) as i16
+}
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+// material and piece-square scores for the middlegame and the endgame,
+// blended by how much material is left
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
 pub struct FastEval {
     piece_counts: [[u8; 5]; 2],
-    score: i16,
-    king_early: i16,
-    king_late: i16,
-}
-impl Default for FastEval {
-    fn default() -> Self {
-        FastEval {
-            piece_counts: [[0; 5]; 2],
-            score: 0,
-            king_early: 0,
-            king_late: 0,
-        }
-    }
+    early: i16,
+    late: i16,
 }
 impl FastEval {
-    pub fn change(&mut self, add: bool, sq: Sq, pos: Pos) {
+    pub fn change(&mut self, add: bool, sq: Sq, pos: Pos, params: &EvalParams) {
         let pc = match sq {
             Sq(Some(pc)) => pc,
             Sq(None) => return,
         };
-        let typ_val = TYP_VALS[pc.typ as usize];
-
-        let pos_val = match pc.typ {
-            Type::Pawn => PAWN_TBL,
-            Type::Knight => KNIGHT_TBL,
-            Type::Bishop => BISHOP_TBL,
-            Type::Rook => ROOK_TBL,
-            Type::Queen => QUEEN_TBL,
-            Type::King => KING_TBL,
-        }[rel_y(pc.clr, pos.y) as usize][pos.x as usize];
-
-        let score = typ_val + pos_val as i16;
-
-        let mut diff = match pc.clr {
-            Color::White => score,
-            Color::Black => -score,
-        };
-        if !add {
-            diff = -diff;
+        let typ = pc.typ as usize;
+        let val = params.piece_values[typ];
+        let mut early = val + tbl_value(&params.pst_early[typ], pc, pos);
+        let mut late = val + tbl_value(&params.pst_late[typ], pc, pos);
+        if (pc.clr == Color::Black) == add {
+            early = -early;
+            late = -late;
         }
-        if pc.typ == Type::King {
-            self.king_early += diff;
-        } else {
-            self.score += diff;
+        self.early += early;
+        self.late += late;
+        if pc.typ != Type::King {
+            let count = &mut self.piece_counts[pc.clr as usize][pc.typ as usize];
+            *count = if add { *count + 1 } else { *count - 1 };
         }
     }
+    // MAX_PHASE at the start of the game down to 0 once the pieces are
+    // traded off. promotions can push it past the start, so it's capped
+    pub fn phase(&self) -> i32 {
+        let phase = self
+            .piece_counts
+            .iter()
+            .flat_map(|counts| counts.iter().zip(&PHASE_WEIGHTS))
+            .map(|(&n, w)| n as i32 * w)
+            .sum();
+        cmp::min(phase, MAX_PHASE)
+    }
+    pub fn pair(&self) -> Pair {
+        (self.early, self.late)
+    }
     pub fn score(&self) -> i16 {
-        return self.score + self.king_early;
+        taper(self.early, self.late, self.phase())
+    }
+}
+
+// (middlegame, endgame) halves of a score, tapered at the end
+pub(crate) type Pair = (i16, i16);
+pub(crate) fn add(a: Pair, b: Pair) -> Pair {
+    (a.0 + b.0, a.1 + b.1)
+}
+pub(crate) fn sub(a: Pair, b: Pair) -> Pair {
+    (a.0 - b.0, a.1 - b.1)
+}
+
+impl State {
+    // piece-square bonus from the piece's own side, in the middlegame
+    pub fn pst_value(&self, pc: Piece, pos: Pos) -> i16 {
+        tbl_value(&self.eval_params().pst_early[pc.typ as usize], pc, pos)
+    }
+    // squares attacked by clr's pawns
+    fn pawn_cover(&self, clr: Color) -> BitBoard {
+        BitIter(self.pieces(clr, Type::Pawn)).fold(0, |acc, pos| acc | pawn_attacks(clr, pos))
+    }
+    // mobility of clr's pieces, and the danger they put the enemy king in
+    // by attacking the squares around it, worse the more of them join in
+    pub(crate) fn activity(&self, clr: Color) -> (Pair, i16) {
+        let params = self.eval_params();
+        let occ = self.occupied_all();
+        // squares guarded by enemy pawns don't really count
+        let area = !self.occupied(clr) & !self.pawn_cover(clr.other());
+        let their_king = *self.get_king_pos(clr.other());
+        let zone = king_attacks(their_king) | pos_bit(their_king);
+        let types = [Type::Knight, Type::Bishop, Type::Rook, Type::Queen];
+        let mut mobility = (0, 0);
+        let (mut attackers, mut units) = (0, 0);
+        for (i, &typ) in types.iter().enumerate() {
+            for pos in BitIter(self.pieces(clr, typ)) {
+                let attacks = match typ {
+                    Type::Knight => knight_attacks(pos),
+                    Type::Bishop => bishop_attacks(pos, occ),
+                    Type::Rook => rook_attacks(pos, occ),
+                    _ => queen_attacks(pos, occ),
+                };
+                let moves = (attacks & area).count_ones() as i16 - params.mobility_base[i];
+                let (early, late) = params.mobility[i];
+                mobility = add(mobility, (moves * early, moves * late));
+                let hits = (attacks & zone).count_ones() as i32;
+                if hits > 0 {
+                    attackers += 1;
+                    units += hits * params.king_attack_units[i] as i32;
+                }
+            }
+        }
+        let danger = if attackers >= 2 {
+            cmp::min(units * units / 4, params.king_danger_max as i32) as i16
+        } else {
+            0
+        };
+        (mobility, danger)
+    }
+    // pawns in front of clr's king, enemy pawns coming at it and open
+    // files to it. only matters with pieces left to attack it
+    pub(crate) fn king_shelter(&self, clr: Color) -> i16 {
+        let params = self.eval_params();
+        let king = *self.get_king_pos(clr);
+        let ours = self.pieces(clr, Type::Pawn);
+        let theirs = self.pieces(clr.other(), Type::Pawn);
+        let ahead = ranks_ahead(clr, king.y);
+        // ranks between the king and the nearest pawn in pawns
+        let nearest = |pawns: BitBoard| {
+            BitIter(pawns)
+                .map(|pos| (rel_y(clr, pos.y) - rel_y(clr, king.y)) as usize)
+                .min()
+        };
+        let mut score = 0;
+        let files = (king.x - 1).max(0)..=(king.x + 1).min(BOARD_DIM.x - 1);
+        for x in files {
+            let file = file_bits(x);
+            if let Some(d) = nearest(ours & file & ahead) {
+                score += params.shield.get(d).copied().unwrap_or(0);
+            }
+            if let Some(d) = nearest(theirs & file & ahead) {
+                score += params.storm.get(d).copied().unwrap_or(0);
+            }
+            if ours & file == 0 {
+                score += if theirs & file == 0 {
+                    params.king_open
+                } else {
+                    params.king_semi_open
+                };
+            }
+        }
+        score
+    }
+    // rooks on files free of pawns, and two bishops
+    pub(crate) fn piece_bonuses(&self, clr: Color) -> Pair {
+        let params = self.eval_params();
+        let ours = self.pieces(clr, Type::Pawn);
+        let all = ours | self.pieces(clr.other(), Type::Pawn);
+        let mut score = (0, 0);
+        for pos in BitIter(self.pieces(clr, Type::Rook)) {
+            let file = file_bits(pos.x);
+            if all & file == 0 {
+                score = add(score, params.rook_open);
+            } else if ours & file == 0 {
+                score = add(score, params.rook_semi_open);
+            }
+        }
+        if self.pieces(clr, Type::Bishop).count_ones() >= 2 {
+            score = add(score, params.bishop_pair);
+        }
+        score
+    }
+    // the full evaluation from white's side. fast_score is the material
+    // and piece-square part of it, see eval_trace for the rest
+    pub fn eval(&self, pawns: &mut PawnTable) -> i16 {
+        let [white, black] = [Color::White, Color::Black].map(|clr| self.activity(clr));
+        // everything but material, the tables and the pawns, from clr's side
+        let side = |clr, (mobility, _): (Pair, i16), (_, danger): (Pair, i16)| {
+            let safety = (self.king_shelter(clr) - danger, 0);
+            add(add(mobility, safety), self.piece_bonuses(clr))
+        };
+        let positional = sub(
+            side(Color::White, white, black),
+            side(Color::Black, black, white),
+        );
+        let (early, late) = add(add(self.fast_pair(), self.pawn_pair(pawns)), positional);
+        taper(early, late, self.phase())
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
 
     // just make sure our value of checkmate isn't too low for constructed positions
     #[test]
     fn checkmate_value() {
         assert!(CHECKMATE / TYP_VALS[Type::Queen as usize] >= 16);
     }
+    #[test]
+    fn eval_capped() {
+        // nine queens and every other piece against a bare king
+        let state: State = str::parse("7k/8/8/8/8/8/QQRRBBNN/QQQQQQQK b - - 0 1").unwrap();
+        assert_eq!(state.fast_score(), EVAL_MAX);
+        assert_eq!(state.eval(&mut PawnTable::new(1)), EVAL_MAX);
+    }
+    #[test]
+    fn tapered() {
+        let state = State::default();
+        assert_eq!(state.phase(), MAX_PHASE);
+        assert_eq!(state.fast_score(), 0);
+        // the king belongs in the middle once the pieces are gone
+        let corner: State = str::parse("8/8/8/8/3k4/8/4P3/7K w - - 0 1").unwrap();
+        let center: State = str::parse("8/8/8/8/3k4/8/4P3/4K3 w - - 0 1").unwrap();
+        assert_eq!(corner.phase(), 0);
+        assert!(center.fast_score() > corner.fast_score());
+        // but not while the pieces are on
+        let center: State =
+            str::parse("rnbqkbnr/pppppppp/8/8/4K3/8/PPPPPPPP/RNBQ1BNR w kq - 0 1").unwrap();
+        assert!(center.fast_score() < state.fast_score());
+        // halfway there, without the minor pieces and a rook each
+        let state: State = str::parse("3qk2r/pppppppp/8/8/8/8/PPPPPPPP/3QK2R w - - 0 1").unwrap();
+        assert_eq!(state.phase(), MAX_PHASE / 2);
+    }
+    #[test]
+    fn incremental() {
+        let mut state: State = str::parse(testpos::KIWIPETE).unwrap();
+        for mv in state.gen_moves() {
+            state.make_move(mv);
+            let fresh: State = str::parse(&state.to_string()).unwrap();
+            assert_eq!(state.fast_score(), fresh.fast_score());
+            assert_eq!(state.phase(), fresh.phase());
+            state.unmake_move();
+        }
+    }
+    // the same position with the colors swapped
+    fn mirror(fen: &str) -> State {
+        let mut fields = fen.split(' ');
+        let swap = |c: char| match c.is_ascii_uppercase() {
+            true => c.to_ascii_lowercase(),
+            false => c.to_ascii_uppercase(),
+        };
+        let board: Vec<String> = fields
+            .next()
+            .unwrap()
+            .split('/')
+            .rev()
+            .map(|row| row.chars().map(swap).collect())
+            .collect();
+        let turn = if fields.next() == Some("w") { "b" } else { "w" };
+        str::parse(&format!("{} {} - - 0 1", board.join("/"), turn)).unwrap()
+    }
+    fn eval(fen: &str) -> i16 {
+        let state: State = str::parse(fen).unwrap();
+        state.eval(&mut PawnTable::new(1))
+    }
+
+    #[test]
+    fn symmetric() {
+        for fen in [
+            testpos::YOUR_MOVE,
+            testpos::KIWIPETE,
+            testpos::POS_4,
+            testpos::POS_5,
+            testpos::POS_6,
+            testpos::DUB_M8,
+        ] {
+            let flipped = mirror(fen).eval(&mut PawnTable::new(1));
+            assert_eq!(eval(fen), -flipped);
+        }
+    }
+    #[test]
+    fn king_safety() {
+        let home: State = str::parse("6k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1").unwrap();
+        let pushed: State = str::parse("6k1/5ppp/8/8/8/5P1P/6P1/6K1 w - - 0 1").unwrap();
+        let open: State = str::parse("6k1/5ppp/8/8/8/8/5P1P/6K1 w - - 0 1").unwrap();
+        let shelter = |state: &State| state.king_shelter(Color::White);
+        assert!(shelter(&home) > shelter(&pushed));
+        assert!(shelter(&pushed) > shelter(&open));
+        // a queen and a knight around the king, a lone queen is no danger
+        let state: State = str::parse("6k1/5ppp/8/8/8/5N2/5PPq/6K1 w - - 0 1").unwrap();
+        assert_eq!(state.activity(Color::Black).1, 0);
+        let state: State = str::parse("6k1/5ppp/8/8/8/5n2/5PPq/6K1 w - - 0 1").unwrap();
+        assert!(state.activity(Color::Black).1 > 0);
+    }
+    #[test]
+    fn pieces() {
+        let bonuses = |fen| {
+            let state: State = str::parse(fen).unwrap();
+            state.piece_bonuses(Color::White)
+        };
+        assert_eq!(bonuses("4k3/p7/8/8/8/8/8/R3K3 w - - 0 1"), ROOK_SEMI_OPEN);
+        assert_eq!(bonuses("4k3/8/8/8/8/8/8/R3K3 w - - 0 1"), ROOK_OPEN);
+        assert_eq!(bonuses("4k3/8/8/8/8/8/P7/R3K3 w - - 0 1"), (0, 0));
+        assert_eq!(bonuses("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1"), BISHOP_PAIR);
+        // the knight in the corner has two squares, in the middle eight
+        let rim: State = str::parse("4k3/8/8/8/8/8/8/N3K3 w - - 0 1").unwrap();
+        let center: State = str::parse("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1").unwrap();
+        assert!(center.activity(Color::White).0 > rim.activity(Color::White).0);
+    }
 }
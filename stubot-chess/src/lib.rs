@@ -4,17 +4,32 @@ extern crate std;
 #[macro_use]
 extern crate alloc;
 
+mod bitboard;
 mod board;
 mod eval;
+mod movelist;
+mod params;
+mod pawns;
 mod perft;
 mod rules;
+mod san;
+mod see;
 mod structs;
 pub mod testpos;
+mod trace;
+mod zobrist;
 
+use bitboard::*;
 use board::*;
 use eval::*;
+use params::*;
 
-pub use board::{show_iter, State};
+pub use bitboard::{BitBoard, BitIter};
+pub use board::{is_null, show_iter, State};
 pub use eval::{mate_ply, CHECKMATE, DRAW, MATE_BOUND};
+pub use movelist::*;
+pub use params::{EvalParams, ParamsError};
+pub use pawns::PawnTable;
 pub use perft::Perft;
 pub use structs::*;
+pub use trace::{EvalTrace, TraceTerm};
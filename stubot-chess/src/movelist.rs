@@ -0,0 +1,315 @@
+use super::*;
+
+use alloc::vec::Vec;
+use core::ops::{Deref, DerefMut};
+
+// no legal chess position has more than 218 moves
+pub const MAX_MOVES: usize = 256;
+
+// filler for unused slots, never read
+const NO_MOVE: Move = Move {
+    a: Pos { x: 0, y: 0 },
+    b: Pos { x: 0, y: 0 },
+    capture: None,
+    extra: None,
+};
+
+// fixed-capacity move list that lives on the stack, so movegen never
+// touches the heap. derefs to a slice of the moves added so far
+#[derive(Clone)]
+pub struct MoveList {
+    moves: [Move; MAX_MOVES],
+    len: usize,
+}
+
+impl MoveList {
+    pub fn new() -> Self {
+        MoveList {
+            moves: [NO_MOVE; MAX_MOVES],
+            len: 0,
+        }
+    }
+    pub fn push(&mut self, mv: Move) {
+        self.moves[self.len] = mv;
+        self.len += 1;
+    }
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+    pub fn swap_remove(&mut self, idx: usize) -> Move {
+        let mv = self[idx];
+        self.len -= 1;
+        self.moves[idx] = self.moves[self.len];
+        mv
+    }
+    pub fn retain(&mut self, mut keep: impl FnMut(&Move) -> bool) {
+        let mut kept = 0;
+        for i in 0..self.len {
+            if keep(&self.moves[i]) {
+                self.moves[kept] = self.moves[i];
+                kept += 1;
+            }
+        }
+        self.len = kept;
+    }
+}
+
+impl Default for MoveList {
+    fn default() -> Self {
+        MoveList::new()
+    }
+}
+
+impl Deref for MoveList {
+    type Target = [Move];
+    fn deref(&self) -> &[Move] {
+        &self.moves[..self.len]
+    }
+}
+impl DerefMut for MoveList {
+    fn deref_mut(&mut self) -> &mut [Move] {
+        &mut self.moves[..self.len]
+    }
+}
+
+impl core::fmt::Debug for MoveList {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+pub struct MoveListIter {
+    list: MoveList,
+    idx: usize,
+}
+impl Iterator for MoveListIter {
+    type Item = Move;
+    fn next(&mut self) -> Option<Move> {
+        let mv = self.list.get(self.idx).copied();
+        self.idx += 1;
+        mv
+    }
+}
+impl IntoIterator for MoveList {
+    type Item = Move;
+    type IntoIter = MoveListIter;
+    fn into_iter(self) -> MoveListIter {
+        MoveListIter { list: self, idx: 0 }
+    }
+}
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a Move;
+    type IntoIter = core::slice::Iter<'a, Move>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// where a picker keeps the moves of its stage and their scores. it only
+// grows as big as the positions it sees need, and the search keeps one per
+// ply between nodes, so a search frame doesn't carry a whole MoveList
+#[derive(Clone, Debug, Default)]
+pub struct PickerBuf(Vec<(Move, i16)>);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+    HashMove,
+    Captures,
+    Killers,
+    Quiets,
+    Done,
+}
+
+// hands out moves a stage at a time, so nothing past the stage that caused
+// a cutoff gets generated. moves are pseudo legal, and within a stage the
+// highest scoring one comes first
+pub struct MovePicker {
+    stage: Stage,
+    // false for quiescence, which stops after the captures
+    quiets: bool,
+    hash_mv: Option<Move>,
+    // killers, then the counter move
+    refutations: [Option<Move>; 3],
+    refutation_idx: usize,
+    // buf is generated and scored for the current stage
+    ready: bool,
+}
+
+impl MovePicker {
+    pub fn new(hash_mv: Option<Move>, killers: [Option<Move>; 2], counter: Option<Move>) -> Self {
+        let [first, second] = killers;
+        MovePicker {
+            stage: Stage::HashMove,
+            quiets: true,
+            hash_mv,
+            refutations: [first, second, counter],
+            refutation_idx: 0,
+            ready: false,
+        }
+    }
+    // captures and promotions only
+    pub fn captures() -> Self {
+        MovePicker {
+            stage: Stage::Captures,
+            quiets: false,
+            ..MovePicker::new(None, [None; 2], None)
+        }
+    }
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+
+    // score ranks the moves of the current stage, higher goes first. buf
+    // must be the same one every call, and not shared with another picker
+    // that's still in use
+    pub fn next(
+        &mut self,
+        state: &mut State,
+        buf: &mut PickerBuf,
+        mut score: impl FnMut(&mut State, Move) -> i16,
+    ) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::HashMove => {
+                    self.stage = Stage::Captures;
+                    if let Some(mv) = self.hash_mv.filter(|&mv| state.is_pseudo_legal(mv)) {
+                        return Some(mv);
+                    }
+                }
+                Stage::Captures | Stage::Quiets => {
+                    if !self.ready {
+                        let list = &mut buf.0;
+                        list.clear();
+                        if self.stage == Stage::Captures {
+                            state.add_sudo_captures(&mut |mv| list.push((mv, 0)));
+                        } else {
+                            state.add_sudo_quiets(&mut |mv| list.push((mv, 0)));
+                        }
+                        for entry in list.iter_mut() {
+                            entry.1 = score(state, entry.0);
+                        }
+                        self.ready = true;
+                    }
+                    if let Some(mv) = self.pick_best(buf) {
+                        return Some(mv);
+                    }
+                    self.ready = false;
+                    self.stage = match self.stage {
+                        Stage::Captures if self.quiets => Stage::Killers,
+                        _ => Stage::Done,
+                    };
+                }
+                Stage::Killers => {
+                    while let Some(&refutation) = self.refutations.get(self.refutation_idx) {
+                        let tried = &self.refutations[..self.refutation_idx];
+                        self.refutation_idx += 1;
+                        // the counter move may repeat a killer
+                        let fresh = |mv| {
+                            Some(mv) != self.hash_mv && is_quiet(mv) && !tried.contains(&Some(mv))
+                        };
+                        if let Some(mv) =
+                            refutation.filter(|&mv| fresh(mv) && state.is_pseudo_legal(mv))
+                        {
+                            return Some(mv);
+                        }
+                    }
+                    self.stage = Stage::Quiets;
+                }
+                Stage::Done => return None,
+            }
+        }
+    }
+
+    // selection sort one step at a time, cheap when we cut off early
+    fn pick_best(&mut self, buf: &mut PickerBuf) -> Option<Move> {
+        let list = &mut buf.0;
+        while !list.is_empty() {
+            let mut best = 0;
+            for i in 1..list.len() {
+                if list[i].1 > list[best].1 {
+                    best = i;
+                }
+            }
+            let (mv, _) = list.swap_remove(best);
+            // the hash move, killers and counter move were already handed out
+            let tried = Some(mv) == self.hash_mv
+                || (self.stage == Stage::Quiets && self.refutations.contains(&Some(mv)));
+            if !tried {
+                return Some(mv);
+            }
+        }
+        None
+    }
+}
+
+// neither a capture nor a promotion
+pub fn is_quiet(mv: Move) -> bool {
+    mv.capture.is_none() && !matches!(mv.extra, Some(MvExtra::Promote(_)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{testpos::*, *};
+
+    #[test]
+    fn list_ops() {
+        let mut state = State::default();
+        let mut list = state.gen_moves();
+        assert_eq!(list.len(), 20);
+        let first = list[0];
+        assert_eq!(list.swap_remove(0), first);
+        assert_eq!(list.len(), 19);
+        list.retain(|mv| mv.a.y == 1);
+        assert_eq!(list.len(), 16);
+        assert!(list.into_iter().all(|mv| mv != first));
+    }
+    #[test]
+    fn picker_covers_movegen() {
+        for fen in [KIWIPETE, POS_3, POS_4, POS_5, POS_6, DUB_M8] {
+            let mut state: State = str::parse(fen).unwrap();
+            let all = state.gen_sudo_moves();
+            // a real hash move, a bogus killer, a real killer and a counter
+            // move that repeats it
+            let hash = all[all.len() / 2];
+            let quiet = all.iter().copied().rfind(|&mv| is_quiet(mv));
+            let bogus = Move {
+                a: str::parse("a1").unwrap(),
+                b: str::parse("h8").unwrap(),
+                capture: None,
+                extra: None,
+            };
+            let mut picker = MovePicker::new(Some(hash), [Some(bogus), quiet], quiet);
+            let mut picked = vec![];
+            let mut stages = vec![];
+            let mut buf = PickerBuf::default();
+            while let Some(mv) = picker.next(&mut state, &mut buf, |_, _| 0) {
+                picked.push(mv);
+                stages.push(picker.stage());
+            }
+            assert_eq!(picked[0], hash);
+            assert_eq!(picked.len(), all.len(), "{}", fen);
+            assert!(all.iter().all(|mv| picked.contains(mv)));
+            assert!(!picked.contains(&bogus));
+            // captures come before quiets
+            let first_quiet = picked.iter().position(|&mv| is_quiet(mv) && mv != hash);
+            let last_capture = picked.iter().rposition(|&mv| !is_quiet(mv) && mv != hash);
+            if let (Some(q), Some(c)) = (first_quiet, last_capture) {
+                assert!(c < q);
+            }
+        }
+    }
+    #[test]
+    fn picker_scores() {
+        let mut state: State = str::parse(KIWIPETE).unwrap();
+        let mut picker = MovePicker::captures();
+        let mut buf = PickerBuf::default();
+        let victim = |mv: Move| mv.capture.map_or(0, |typ| typ as i16);
+        let mut last = i16::MAX;
+        while let Some(mv) = picker.next(&mut state, &mut buf, |_, mv| victim(mv)) {
+            assert!(!is_quiet(mv));
+            assert!(victim(mv) <= last);
+            last = victim(mv);
+        }
+        assert_eq!(picker.stage(), Stage::Done);
+    }
+}
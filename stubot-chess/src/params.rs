@@ -0,0 +1,349 @@
+use super::*;
+use pawns::*;
+
+use alloc::string::*;
+use alloc::vec::Vec;
+use core::cmp;
+use core::fmt;
+use core::str;
+
+// every weight the evaluation uses. the default is the built-in one, the
+// text form lets them be changed without rebuilding
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalParams {
+    pub piece_values: [i16; 6],
+    // by type, from the piece's own side with its back rank first
+    pub pst_early: [ScoreTable; 6],
+    pub pst_late: [ScoreTable; 6],
+    pub doubled: Pair,
+    pub isolated: Pair,
+    pub backward: Pair,
+    pub connected: [i16; 8],
+    pub passed_early: [i16; 8],
+    pub passed_late: [i16; 8],
+    pub passed_king_theirs: i16,
+    pub passed_king_ours: i16,
+    // knights, bishops, rooks and queens
+    pub mobility: [Pair; 4],
+    pub mobility_base: [i16; 4],
+    pub king_attack_units: [i16; 4],
+    pub king_danger_max: i16,
+    pub shield: [i16; 3],
+    pub storm: [i16; 4],
+    pub king_semi_open: i16,
+    pub king_open: i16,
+    pub rook_semi_open: Pair,
+    pub rook_open: Pair,
+    pub bishop_pair: Pair,
+}
+
+// the built-in weights
+pub(crate) static DEFAULT_PARAMS: EvalParams = EvalParams {
+    piece_values: TYP_VALS,
+    pst_early: [
+        PAWN_TBL, KNIGHT_TBL, BISHOP_TBL, ROOK_TBL, QUEEN_TBL, KING_TBL,
+    ],
+    pst_late: [
+        PAWN_LATE_TBL,
+        KNIGHT_LATE_TBL,
+        BISHOP_LATE_TBL,
+        ROOK_LATE_TBL,
+        QUEEN_LATE_TBL,
+        KING_LATE_TBL,
+    ],
+    doubled: DOUBLED,
+    isolated: ISOLATED,
+    backward: BACKWARD,
+    connected: CONNECTED,
+    passed_early: PASSED_EARLY,
+    passed_late: PASSED_LATE,
+    passed_king_theirs: PASSED_KING_THEIRS,
+    passed_king_ours: PASSED_KING_OURS,
+    mobility: MOBILITY,
+    mobility_base: MOBILITY_BASE,
+    king_attack_units: KING_ATTACK_UNITS,
+    king_danger_max: KING_DANGER_MAX,
+    shield: SHIELD,
+    storm: STORM,
+    king_semi_open: KING_SEMI_OPEN,
+    king_open: KING_OPEN,
+    rook_semi_open: ROOK_SEMI_OPEN,
+    rook_open: ROOK_OPEN,
+    bishop_pair: BISHOP_PAIR,
+};
+impl Default for EvalParams {
+    fn default() -> Self {
+        DEFAULT_PARAMS.clone()
+    }
+}
+
+const PST_EARLY_NAMES: [&str; 6] = [
+    "pawn_pst_early",
+    "knight_pst_early",
+    "bishop_pst_early",
+    "rook_pst_early",
+    "queen_pst_early",
+    "king_pst_early",
+];
+const PST_LATE_NAMES: [&str; 6] = [
+    "pawn_pst_late",
+    "knight_pst_late",
+    "bishop_pst_late",
+    "rook_pst_late",
+    "queen_pst_late",
+    "king_pst_late",
+];
+
+fn pair(p: &mut Pair) -> Vec<&mut i16> {
+    vec![&mut p.0, &mut p.1]
+}
+fn table(tbl: &mut ScoreTable) -> Vec<&mut i16> {
+    tbl.iter_mut().flatten().collect()
+}
+
+impl EvalParams {
+    // every weight by name, in the order they're written
+    fn fields(&mut self) -> Vec<(&'static str, Vec<&mut i16>)> {
+        let mut fields = vec![("piece_values", self.piece_values.iter_mut().collect())];
+        for (tbl, &name) in self.pst_early.iter_mut().zip(&PST_EARLY_NAMES) {
+            fields.push((name, table(tbl)));
+        }
+        for (tbl, &name) in self.pst_late.iter_mut().zip(&PST_LATE_NAMES) {
+            fields.push((name, table(tbl)));
+        }
+        fields.extend([
+            ("doubled", pair(&mut self.doubled)),
+            ("isolated", pair(&mut self.isolated)),
+            ("backward", pair(&mut self.backward)),
+            ("connected", self.connected.iter_mut().collect()),
+            ("passed_early", self.passed_early.iter_mut().collect()),
+            ("passed_late", self.passed_late.iter_mut().collect()),
+            ("passed_king_theirs", vec![&mut self.passed_king_theirs]),
+            ("passed_king_ours", vec![&mut self.passed_king_ours]),
+            (
+                "mobility",
+                self.mobility.iter_mut().flat_map(pair).collect(),
+            ),
+            ("mobility_base", self.mobility_base.iter_mut().collect()),
+            (
+                "king_attack_units",
+                self.king_attack_units.iter_mut().collect(),
+            ),
+            ("king_danger_max", vec![&mut self.king_danger_max]),
+            ("shield", self.shield.iter_mut().collect()),
+            ("storm", self.storm.iter_mut().collect()),
+            ("king_semi_open", vec![&mut self.king_semi_open]),
+            ("king_open", vec![&mut self.king_open]),
+            ("rook_semi_open", pair(&mut self.rook_semi_open)),
+            ("rook_open", pair(&mut self.rook_open)),
+            ("bishop_pair", pair(&mut self.bishop_pair)),
+        ]);
+        fields
+    }
+
+    // more than any sum the eval adds up can come to, with every term at
+    // its biggest for one side and smallest for the other. the pieces
+    // besides the king are slots, the queen, two rooks, bishops and knights
+    // and eight pawns that can promote to anything
+    fn max_eval(&self) -> i32 {
+        let abs = |v: &i16| (*v as i32).abs();
+        let most = |vals: &[i16]| vals.iter().map(abs).max().unwrap_or(0);
+        let pair = |p: &Pair| cmp::max(abs(&p.0), abs(&p.1));
+        let [pawn, knight, bishop, rook, queen, king] = self.piece_values;
+        // a slot holds one of vals or nothing, sides differ by at most this
+        let slot = |vals: &[i16]| {
+            let hi = vals.iter().map(|&v| cmp::max(v, 0)).max().unwrap_or(0);
+            let lo = vals.iter().map(|&v| cmp::min(v, 0)).min().unwrap_or(0);
+            hi as i32 - lo as i32
+        };
+        let promoted = [pawn, knight, bishop, rook, queen];
+        // the kings cancel out, but not before both are on the board
+        let material = abs(&king)
+            + slot(&[queen])
+            + 2 * (slot(&[rook]) + slot(&[bishop]) + slot(&[knight]))
+            + 8 * slot(&promoted);
+        // the rest can have either sign, so both sides count
+        let tables = self.pst_early.iter().chain(&self.pst_late);
+        let pst = 16 * tables.flatten().map(|row| most(row)).max().unwrap_or(0);
+        let connected = most(&self.connected);
+        let passed = cmp::max(most(&self.passed_early), most(&self.passed_late));
+        let structure = pair(&self.doubled)
+            + cmp::max(pair(&self.isolated), pair(&self.backward))
+            + connected
+            + passed;
+        // a pawn is at most 4 ranks past where it scales, 7 from a king
+        let race = 4 * 7 * (abs(&self.passed_king_theirs) + abs(&self.passed_king_ours));
+        let pawns = 8 * (structure + race);
+        // most squares each of knights, bishops, rooks and queens reach
+        let reach = [8, 13, 14, 27];
+        let moving = |i: usize| (reach[i] + abs(&self.mobility_base[i])) * pair(&self.mobility[i]);
+        let any = (0..4).map(moving).max().unwrap_or(0);
+        let mobility = moving(3) + 2 * (moving(2) + moving(1) + moving(0)) + 8 * any;
+        let safety = abs(&self.king_danger_max)
+            + 3 * (most(&self.shield)
+                + most(&self.storm)
+                + cmp::max(abs(&self.king_open), abs(&self.king_semi_open)));
+        let rooks = 10 * cmp::max(pair(&self.rook_open), pair(&self.rook_semi_open));
+        let bonuses = rooks + pair(&self.bishop_pair);
+        material + 2 * (pst + pawns + mobility + safety + bonuses)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamsError {
+    Name(String),
+    // a value before any name
+    Number(String),
+    // name, expected count, found count
+    Count(String, usize, usize),
+    // the weights are big enough that the eval could overflow, and how
+    // far it could get
+    Range(i32),
+}
+impl fmt::Display for ParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParamsError::Name(s) => write!(f, "unknown eval param '{}'", s),
+            ParamsError::Number(s) => write!(f, "expected an eval param name, got '{}'", s),
+            ParamsError::Count(name, expected, found) => write!(
+                f,
+                "eval param {} takes {} values, got {}",
+                name, expected, found
+            ),
+            ParamsError::Range(max) => write!(
+                f,
+                "eval params could add up to {}, more than an eval can hold",
+                max
+            ),
+        }
+    }
+}
+
+// each weight's name, then its values. tables get a row per line. the
+// parser only cares about the order, and # starts a comment
+impl fmt::Display for EvalParams {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut params = self.clone();
+        for (name, values) in params.fields() {
+            let row_len = if values.len() == 64 { 8 } else { values.len() };
+            writeln!(f, "{}", name)?;
+            for row in values.chunks(row_len) {
+                let row: Vec<_> = row.iter().map(|v| v.to_string()).collect();
+                writeln!(f, "    {}", row.join(" "))?;
+            }
+        }
+        Ok(())
+    }
+}
+// params missing from the text keep their default
+impl str::FromStr for EvalParams {
+    type Err = ParamsError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut params = EvalParams::default();
+        let mut fields = params.fields();
+        let words = s
+            .lines()
+            .flat_map(|line| line.split('#').next().unwrap_or("").split_whitespace());
+        // the field being filled in, and how many values it has so far
+        let mut cur: Option<(usize, usize)> = None;
+        let finish = |fields: &[(&str, Vec<&mut i16>)], cur: Option<(usize, usize)>| match cur {
+            Some((i, n)) if n != fields[i].1.len() => Err(ParamsError::Count(
+                fields[i].0.to_string(),
+                fields[i].1.len(),
+                n,
+            )),
+            _ => Ok(()),
+        };
+        for word in words {
+            match (str::parse::<i16>(word), cur) {
+                (Ok(val), Some((i, n))) => {
+                    if let Some(slot) = fields[i].1.get_mut(n) {
+                        **slot = val;
+                    }
+                    cur = Some((i, n + 1));
+                }
+                (Ok(_), None) => return Err(ParamsError::Number(word.to_string())),
+                (Err(_), _) => {
+                    finish(&fields, cur)?;
+                    let i = fields
+                        .iter()
+                        .position(|(name, _)| *name == word)
+                        .ok_or_else(|| ParamsError::Name(word.to_string()))?;
+                    cur = Some((i, 0));
+                }
+            }
+        }
+        finish(&fields, cur)?;
+        drop(fields);
+        // evals are added up in i16s, which every partial sum has to fit
+        match params.max_eval() {
+            max if max > i16::MAX as i32 => Err(ParamsError::Range(max)),
+            _ => Ok(params),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::sync::Arc;
+
+    #[test]
+    fn round_trip() {
+        let params = EvalParams::default();
+        let text = params.to_string();
+        assert!(text.starts_with("piece_values\n    100 320 330 500 900 0\n"));
+        assert_eq!(str::parse::<EvalParams>(&text), Ok(params));
+    }
+    #[test]
+    fn partial() {
+        let text = "# cheaper knights\nbishop_pair 40 60\npiece_values 100 300 330\n500 900 0";
+        let params: EvalParams = str::parse(text).unwrap();
+        assert_eq!(params.piece_values[Type::Knight as usize], 300);
+        assert_eq!(params.bishop_pair, (40, 60));
+        assert_eq!(params.pst_late, EvalParams::default().pst_late);
+    }
+    #[test]
+    fn bad_params() {
+        let parse = str::parse::<EvalParams>;
+        assert_eq!(parse("5"), Err(ParamsError::Number("5".to_string())));
+        assert_eq!(
+            parse("queens 9"),
+            Err(ParamsError::Name("queens".to_string()))
+        );
+        let short = ParamsError::Count("shield".to_string(), 3, 2);
+        assert_eq!(parse("shield 1 2\nking_open 4"), Err(short.clone()));
+        assert_eq!(parse("shield 1 2"), Err(short));
+        assert!(parse("king_open 1 2").is_err());
+        assert!(EvalParams::default().max_eval() <= i16::MAX as i32);
+        for big in [
+            "piece_values 100 320 330 500 9000 0",
+            "mobility 4 4 4 5 2 4 900 2",
+        ] {
+            assert!(matches!(parse(big), Err(ParamsError::Range(_))), "{}", big);
+        }
+        assert!(matches!(
+            parse("piece_values 0 0 0 0 0 -32000"),
+            Err(ParamsError::Range(_))
+        ));
+    }
+    #[test]
+    fn set_params() {
+        let mut state: State = str::parse(testpos::KIWIPETE).unwrap();
+        let before = state.fast_score();
+        let params = Arc::new(EvalParams {
+            pst_early: [[[0; 8]; 8]; 6],
+            ..Default::default()
+        });
+        state.set_eval_params(params.clone());
+        assert_ne!(state.fast_score(), before);
+        for mv in state.gen_moves() {
+            state.make_move(mv);
+            let mut fresh: State = str::parse(&state.to_string()).unwrap();
+            fresh.set_eval_params(params.clone());
+            assert_eq!(state.fast_score(), fresh.fast_score());
+            assert_eq!(state.eval_trace().total, state.eval(&mut PawnTable::new(1)));
+            state.unmake_move();
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use super::*;
+use rules::pawn_dir;
+
+use alloc::vec::Vec;
+use core::cmp;
+
+// (middlegame, endgame) per pawn
+pub(crate) const DOUBLED: (i16, i16) = (-10, -20);
+pub(crate) const ISOLATED: (i16, i16) = (-10, -15);
+pub(crate) const BACKWARD: (i16, i16) = (-8, -10);
+// by relative rank, for a pawn next to or defended by another
+pub(crate) const CONNECTED: [i16; 8] = [0, 3, 5, 8, 12, 20, 30, 0];
+// by relative rank, on top of the piece-square tables
+pub(crate) const PASSED_EARLY: [i16; 8] = [0, 5, 5, 10, 20, 35, 60, 0];
+pub(crate) const PASSED_LATE: [i16; 8] = [0, 10, 10, 20, 40, 65, 100, 0];
+// endgame bonus per square between the enemy king and a passer's stop
+// square, minus our own king's, times how far up the board it is
+pub(crate) const PASSED_KING_THEIRS: i16 = 5;
+pub(crate) const PASSED_KING_OURS: i16 = 2;
+
+fn distance(a: Pos, b: Pos) -> i16 {
+    cmp::max((a.x - b.x).abs(), (a.y - b.y).abs()) as i16
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+struct PawnEntry {
+    key: u64,
+    // white's score minus black's
+    early: i16,
+    late: i16,
+    // passed pawns of both colors
+    passed: BitBoard,
+}
+
+// pawn structure only changes when a pawn moves or gets taken, so nearly
+// every lookup in a search hits. each searcher owns one
+pub struct PawnTable {
+    entries: Vec<PawnEntry>,
+}
+impl PawnTable {
+    // size is in entries, rounded down to a power of 2
+    pub fn new(size: usize) -> Self {
+        let size = match size {
+            0 => 1,
+            n => 1 << (usize::BITS - 1 - n.leading_zeros()),
+        };
+        PawnTable {
+            entries: vec![PawnEntry::default(); size],
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    // entries depend on the eval params, so this has to run when they change
+    pub fn clear(&mut self) {
+        self.entries.fill(PawnEntry::default());
+    }
+    fn entry(&mut self, state: &State) -> PawnEntry {
+        let key = state.pawn_key();
+        let idx = key as usize & (self.entries.len() - 1);
+        // an empty slot has the key of a board without pawns, and an
+        // empty entry is what that board would get anyway
+        if self.entries[idx].key != key {
+            self.entries[idx] = state.pawn_structure();
+        }
+        self.entries[idx]
+    }
+}
+
+impl State {
+    // clr's pawn structure from its own side, and its passed pawns
+    pub(crate) fn pawn_side(&self, clr: Color) -> (Pair, BitBoard) {
+        let params = self.eval_params();
+        let ours = self.pieces(clr, Type::Pawn);
+        let theirs = self.pieces(clr.other(), Type::Pawn);
+        let (mut early, mut late) = (0, 0);
+        let mut passed = 0;
+        for pos in BitIter(ours) {
+            let rank = rel_y(clr, pos.y) as usize;
+            let ahead = ranks_ahead(clr, pos.y);
+            let file = file_bits(pos.x);
+            let adjacent = adjacent_files(pos.x);
+            // only the rear pawn of a stack counts, the front one is
+            // the one that can still be passed
+            let doubled = ours & file & ahead != 0;
+            if doubled {
+                early += params.doubled.0;
+                late += params.doubled.1;
+            }
+            if ours & adjacent == 0 {
+                early += params.isolated.0;
+                late += params.isolated.1;
+            } else if ours & adjacent & !ahead == 0
+                && pawn_attacks(clr, pos + pawn_dir(clr)) & theirs != 0
+            {
+                // nothing can come up to defend it, and it can't
+                // advance without being taken
+                early += params.backward.0;
+                late += params.backward.1;
+            }
+            let phalanx = ours & adjacent & rank_bits(pos.y);
+            if phalanx != 0 || pawn_attacks(clr.other(), pos) & ours != 0 {
+                early += params.connected[rank];
+                late += params.connected[rank];
+            }
+            if !doubled && theirs & (file | adjacent) & ahead == 0 {
+                passed |= pos_bit(pos);
+                early += params.passed_early[rank];
+                late += params.passed_late[rank];
+            }
+        }
+        ((early, late), passed)
+    }
+    // everything about the pawns that only depends on the pawns
+    fn pawn_structure(&self) -> PawnEntry {
+        let (white, white_passed) = self.pawn_side(Color::White);
+        let (black, black_passed) = self.pawn_side(Color::Black);
+        PawnEntry {
+            key: self.pawn_key(),
+            early: white.0 - black.0,
+            late: white.1 - black.1,
+            passed: white_passed | black_passed,
+        }
+    }
+    // endgame bonus for clr's passed pawns the kings are too far from or
+    // close enough to help, which keeps it out of the table
+    pub(crate) fn passer_race(&self, clr: Color, passed: BitBoard) -> i16 {
+        let params = self.eval_params();
+        let our_king = *self.get_king_pos(clr);
+        let their_king = *self.get_king_pos(clr.other());
+        let mut bonus = 0;
+        for pos in BitIter(passed & self.pieces(clr, Type::Pawn)) {
+            let scale = rel_y(clr, pos.y) as i16 - 2;
+            if scale <= 0 {
+                continue;
+            }
+            let stop = pos + pawn_dir(clr);
+            let race = params.passed_king_theirs * distance(their_king, stop)
+                - params.passed_king_ours * distance(our_king, stop);
+            bonus += scale * race;
+        }
+        bonus
+    }
+    // the pawns from white's side, before tapering
+    pub(crate) fn pawn_pair(&self, table: &mut PawnTable) -> Pair {
+        let entry = table.entry(self);
+        let race = self.passer_race(Color::White, entry.passed)
+            - self.passer_race(Color::Black, entry.passed);
+        (entry.early, entry.late + race)
+    }
+    // pawn structure from white's side, tapered like fast_score
+    pub fn pawn_score(&self, table: &mut PawnTable) -> i16 {
+        let (early, late) = self.pawn_pair(table);
+        taper(early, late, self.phase())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::string::ToString;
+
+    fn structure(fen: &str) -> PawnEntry {
+        let state: State = str::parse(fen).unwrap();
+        state.pawn_structure()
+    }
+    fn bits(squares: &[&str]) -> BitBoard {
+        squares
+            .iter()
+            .map(|s| pos_bit(str::parse(s).unwrap()))
+            .fold(0, |a, b| a | b)
+    }
+
+    #[test]
+    fn pawn_terms() {
+        let start = State::default().pawn_structure();
+        assert_eq!((start.early, start.late, start.passed), (0, 0, 0));
+        // doubled and isolated on the c file, one isolated passer each
+        let entry = structure("4k3/2p3p1/8/8/8/2P5/2P1P3/4K3 w - - 0 1");
+        assert_eq!(entry.passed, bits(&["e2", "g7"]));
+        assert_eq!(entry.late, DOUBLED.1 + ISOLATED.1);
+        // connected passers for white. black's c pawn is passed and
+        // defended, the d pawn behind it is backward
+        let entry = structure("4k3/8/3p4/2p5/4P3/8/6PP/4K3 w - - 0 1");
+        assert_eq!(entry.passed, bits(&["g2", "h2", "c5"]));
+        let white = ISOLATED.1 + 2 * (CONNECTED[1] + PASSED_LATE[1]);
+        let black = CONNECTED[3] + PASSED_LATE[3] + BACKWARD.1;
+        assert_eq!(entry.late, white - black);
+    }
+    #[test]
+    fn king_proximity() {
+        let caught: State = str::parse("8/2k5/8/1P6/8/8/8/4K3 w - - 0 1").unwrap();
+        let free: State = str::parse("7k/8/8/1P6/8/8/8/4K3 w - - 0 1").unwrap();
+        let mut table = PawnTable::new(4);
+        // same pawns, the kings only count after the lookup
+        assert_eq!(caught.pawn_key(), free.pawn_key());
+        assert!(free.pawn_score(&mut table) > caught.pawn_score(&mut table));
+    }
+    #[test]
+    fn pawn_key() {
+        let mut state: State = str::parse(testpos::KIWIPETE).unwrap();
+        let mut table = PawnTable::new(1 << 6);
+        assert_eq!(table.len(), 64);
+        for mv in state.gen_moves() {
+            let before = state.pawn_key();
+            state.make_move(mv);
+            let fresh: State = str::parse(&state.to_string()).unwrap();
+            assert_eq!(state.pawn_key(), fresh.pawn_key());
+            let moved_pawn = mv.capture == Some(Type::Pawn)
+                || state.idx(mv.b).0.is_some_and(|pc| pc.typ == Type::Pawn)
+                || matches!(mv.extra, Some(MvExtra::Promote(_)));
+            assert_eq!(state.pawn_key() != before, moved_pawn);
+            assert_eq!(state.pawn_score(&mut table), fresh.pawn_score(&mut table));
+            state.unmake_move();
+            assert_eq!(state.pawn_key(), before);
+        }
+    }
+}
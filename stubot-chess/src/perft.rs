@@ -20,45 +20,57 @@ pub struct Perft {
 impl State {
     pub fn perft(&mut self, depth: u32) -> Perft {
         let mut res: Perft = Default::default();
+        debug_assert_eq!(self.key(), self.compute_key());
         if depth == 0 {
             res.nodes = 1;
             return res;
         }
 
         for mv in self.gen_sudo_moves() {
-            #[cfg(test)]
-            let mut cpy = self.clone();
-            self.make_move(mv);
-            if self.is_legal() {
-                if depth == 1 {
-                    res.nodes += 1;
-                    match mv.extra {
-                        Some(MvExtra::Castle(_)) => res.castles += 1,
-                        Some(MvExtra::EnPassant) => res.enps += 1,
-                        Some(MvExtra::Promote(_)) => res.promotions += 1,
-                        None => (),
-                    }
-                    if let Some(_) = mv.capture {
-                        res.caps += 1;
-                    }
-                } else {
-                    res += self.perft(depth - 1);
+            let legal = self.is_legal_move(mv);
+
+            // check the fast legality test against actually making the move
+            #[cfg(debug_assertions)]
+            {
+                #[cfg(test)]
+                let mut cpy = self.clone();
+                self.make_move(mv);
+                debug_assert_eq!(legal, self.is_legal(), "legality of {}", mv);
+                self.unmake_move();
+
+                // kinda expensive unmake comparison test
+                #[cfg(test)]
+                if *self != cpy {
+                    println!("orig:");
+                    println!("{}", cpy.board_string());
+                    println!("then move {}:", mv);
+                    println!("{:?}", mv);
+                    cpy.make_move(mv);
+                    println!("{}", cpy.board_string());
+                    println!("unmade into:");
+                    println!("{}", self.board_string());
+                    panic!("unmake_move did not restore the state");
                 }
             }
-            self.unmake_move();
-
-            // kinda expensive unmake comparison test
-            #[cfg(test)]
-            if *self != cpy {
-                println!("orig:");
-                println!("{}", cpy.board_string());
-                println!("then move {}:", mv);
-                println!("{:?}", mv);
-                cpy.make_move(mv);
-                println!("{}", cpy.board_string());
-                println!("unmade into:");
-                println!("{}", self.board_string());
-                assert!(false);
+
+            if !legal {
+                continue;
+            }
+            if depth == 1 {
+                res.nodes += 1;
+                match mv.extra {
+                    Some(MvExtra::Castle(_)) => res.castles += 1,
+                    Some(MvExtra::EnPassant) => res.enps += 1,
+                    Some(MvExtra::Promote(_)) => res.promotions += 1,
+                    None => (),
+                }
+                if mv.capture.is_some() {
+                    res.caps += 1;
+                }
+            } else {
+                self.make_move(mv);
+                res += self.perft(depth - 1);
+                self.unmake_move();
             }
         }
         res
@@ -93,9 +105,6 @@ impl State {
     }
 }
 
-#[cfg(test)]
-pub use test::*;
-
 #[cfg(test)]
 pub mod test {
     use super::testpos::*;
@@ -172,6 +181,34 @@ pub mod test {
         let mut state: State = str::parse(POS_6).unwrap();
         test_position(&mut state, vec![46, 2079, 89890]);
     }
+    // from the chess960 perft suite, castling rights in Shredder-FEN
+    #[test]
+    fn test_chess960() {
+        let mut state: State =
+            str::parse("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9")
+                .unwrap();
+        test_position(&mut state, vec![21, 528, 12189, 326672]);
+        for (fen, nodes) in [
+            (
+                "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+                vec![21, 807, 18002],
+            ),
+            (
+                "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+                vec![20, 479, 10471],
+            ),
+            (
+                "1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9",
+                vec![28, 1120, 31058],
+            ),
+            (
+                "qnbnr1kr/ppp1b1pp/4p3/3p1p2/8/2NPP3/PPP1BPPP/QNB1R1KR w HEhe - 1 9",
+                vec![29, 899, 26578],
+            ),
+        ] {
+            test_position(&mut str::parse(fen).unwrap(), nodes);
+        }
+    }
     // none of the 6 test positions have imminent mate threats in the first 3
     // nodes. add a game that has mate threats so it can be counted
     #[test]
@@ -1,287 +1,278 @@
 use super::card;
 use super::*;
 
-use core::cmp;
-
 use alloc::string::*;
-use alloc::vec::Vec;
 
-fn pawn_dir(clr: Color) -> Pos {
+pub(crate) fn pawn_dir(clr: Color) -> Pos {
     match clr {
         Color::White => card::N,
         Color::Black => card::S,
     }
 }
+#[derive(Clone, Copy, PartialEq)]
+enum GenKind {
+    All,
+    // captures and promotions
+    Captures,
+    Quiets,
+}
+
 // no trait aliases yet
-trait PosCb: FnMut(Pos) -> bool {}
-impl<T: FnMut(Pos) -> bool> PosCb for T {}
 pub trait MoveAdd: FnMut(Move) {}
 impl<T: FnMut(Move)> MoveAdd for T {}
 
-fn rider(orig: Pos, options: &'static [Pos], mut f: impl PosCb) {
-    for &dir in options {
-        let mut pos = orig + dir;
-        while f(pos) {
-            pos += dir;
-        }
-    }
-}
-fn leaper(orig: Pos, options: &'static [Pos], mut f: impl PosCb) {
-    for &dir in options {
-        f(orig + dir);
-    }
-}
-
-const KNIGHT_OPTS: &[Pos] = &[
-    Pos { x: 1, y: 2 },
-    Pos { x: -1, y: 2 },
-    Pos { x: 1, y: -2 },
-    Pos { x: -1, y: -2 },
-    Pos { x: 2, y: 1 },
-    Pos { x: -2, y: 1 },
-    Pos { x: 2, y: -1 },
-    Pos { x: -2, y: -1 },
-];
-
-const BISHOP_OPTS: &[Pos] = &[
-    Pos { x: 1, y: 1 },
-    Pos { x: 1, y: -1 },
-    Pos { x: -1, y: 1 },
-    Pos { x: -1, y: -1 },
-];
-const ROOK_OPTS: &[Pos] = &[
-    Pos { x: 1, y: 0 },
-    Pos { x: -1, y: 0 },
-    Pos { x: 0, y: 1 },
-    Pos { x: 0, y: -1 },
-];
-
 impl State {
     pub fn in_check(&self, clr: Color) -> bool {
         let king_pos = *self.get_king_pos(clr);
         debug_assert!(*self.idx(king_pos) == Sq::new(clr, Type::King));
         self.is_attacked(king_pos, clr.other())
     }
-    // extends out rays/leaps in reverse to see if someone attacks
     fn is_attacked(&self, orig: Pos, enemy: Color) -> bool {
-        use core::cell::Cell;
-        let enemy_knight = Sq::new(enemy, Type::Knight);
-        for &opt in KNIGHT_OPTS {
-            if self.get(orig + opt) == Some(&enemy_knight) {
-                return true;
-            }
-        }
-        // reverse the pawn attack direction, this is relative to the king
-        let pdir = pawn_dir(enemy.other());
-        let enemy_pawn = Sq::new(enemy, Type::Pawn);
-        for &side in &[card::E, card::W] {
-            if self.get(orig + pdir + side) == Some(&enemy_pawn) {
-                return true;
-            }
-        }
-        let found_attack = &Cell::new(false);
-        let attack = || {
-            found_attack.set(true);
-        };
-        let check = |threat| {
-            move |pos| {
-                let Piece { clr, typ } = match self.get(pos) {
-                    Some(Sq(Some(pc))) => *pc,
-                    // keep scanning if we haven't found a check
-                    Some(Sq(None)) => return !found_attack.get(),
-                    None => return false,
-                };
-
-                if clr == enemy {
-                    if typ == threat || typ == Type::Queen {
-                        attack();
-                    } else if typ == Type::King {
-                        let diff = pos - orig;
-                        // king should be right next to them
-                        if cmp::max(diff.x.abs(), diff.y.abs()) <= 1 {
-                            attack();
-                        }
-                    }
-                }
-                // scan threats bump into pieces
-                false
-            }
-        };
-        rider(orig, BISHOP_OPTS, check(Type::Bishop));
-        rider(orig, ROOK_OPTS, check(Type::Rook));
-
-        found_attack.get()
+        self.is_attacked_by(orig, enemy, self.occupied_all(), !0)
     }
-    // adds the move if there is space, returns whether ray should continue.
-    // return value is unused in some cases.
-    // except if gate(Sq) is false, it just stops early. gate is unused (just
-    // returns true) in some cases.
-    fn try_move(&self, gate: impl Fn(bool) -> bool, add: &mut impl MoveAdd, mut mv: Move) -> bool {
-        if mv.extra == Some(MvExtra::EnPassant) {
-            mv.capture = Some(Type::Pawn);
-        }
-        let b_sq = match self.get(mv.b) {
-            Some(sq) => *sq,
-            None => return false,
-        };
-        if !gate(b_sq.0.is_some()) {
-            return false;
-        }
-
-        let Piece { clr, typ } = match b_sq {
-            Sq(Some(pc)) => pc,
-            Sq(None) => {
-                add(mv);
-                return true;
-            }
-        };
-        if clr != self.turn() {
-            mv.capture = Some(typ);
-            add(mv);
-        }
-        return false;
+    // looks from orig with every piece's attack pattern to see if an enemy
+    // piece of that type is hit. occ is the occupancy to use for sliders,
+    // and only enemy pieces in mask count
+    fn is_attacked_by(&self, orig: Pos, enemy: Color, occ: BitBoard, mask: BitBoard) -> bool {
+        let enemy_pcs = |typ| self.pieces(enemy, typ) & mask;
+        let queens = enemy_pcs(Type::Queen);
+        // reverse the pawn attack direction, this is relative to the target
+        knight_attacks(orig) & enemy_pcs(Type::Knight) != 0
+            || pawn_attacks(enemy.other(), orig) & enemy_pcs(Type::Pawn) != 0
+            || king_attacks(orig) & enemy_pcs(Type::King) != 0
+            || bishop_attacks(orig, occ) & (enemy_pcs(Type::Bishop) | queens) != 0
+            || rook_attacks(orig, occ) & (enemy_pcs(Type::Rook) | queens) != 0
     }
 
     pub fn is_legal(&self) -> bool {
         !self.in_check(self.turn().other())
     }
 
-    pub fn is_legal_move(&mut self, mv: Move) -> bool {
-        self.make_move(mv);
-        // only extra condition for a psuedo move is check
-        let legal = self.is_legal();
-        self.unmake_move();
-        legal
-    }
-
-    // average number of chess moves for Vec::with_capacity
-    pub fn move_count(&self) -> usize {
-        32
+    // only extra condition for a psuedo move is check. instead of making the
+    // move, checks for attacks on the king with the occupancy after it
+    pub fn is_legal_move(&self, mv: Move) -> bool {
+        let clr = self.turn();
+        let mut mv = mv;
+        let mut occ = self.occupied_all() & !pos_bit(mv.a);
+        // a captured piece can't attack anymore
+        let mut captured = pos_bit(mv.b);
+        match mv.extra {
+            Some(MvExtra::EnPassant) => {
+                captured = pos_bit(en_passant_cap(mv));
+                occ &= !captured;
+            }
+            Some(MvExtra::Castle(side)) => {
+                // b might be the rook, see where the king really goes
+                let (src, dst) = self.castle_rook_path(clr, side);
+                mv.b = castle_king_dst(clr, side);
+                captured = 0;
+                occ = (occ & !pos_bit(src)) | pos_bit(dst);
+            }
+            _ => (),
+        }
+        occ |= pos_bit(mv.b);
+        let king = match self.idx(mv.a).0 {
+            Some(Piece {
+                typ: Type::King, ..
+            }) => mv.b,
+            _ => *self.get_king_pos(clr),
+        };
+        !self.is_attacked_by(king, clr.other(), occ, !captured)
     }
 
     // requires a mutable reference, but doesn't actually modify anything
     // (if our code is correct)
-    pub fn gen_moves(&mut self) -> Vec<Move> {
+    pub fn gen_moves(&mut self) -> MoveList {
         let mut moves = self.gen_sudo_moves();
         moves.retain(|&mv| self.is_legal_move(mv));
         moves
     }
 
-    pub fn gen_sudo_moves(&self) -> Vec<Move> {
-        let mut moves = Vec::with_capacity(self.move_count());
+    pub fn gen_sudo_moves(&self) -> MoveList {
+        let mut moves = MoveList::new();
         self.add_sudo_moves(&mut |mv| moves.push(mv));
         moves
     }
 
+    pub fn gen_sudo_captures(&self) -> MoveList {
+        let mut moves = MoveList::new();
+        self.add_sudo_captures(&mut |mv| moves.push(mv));
+        moves
+    }
+
     pub fn add_sudo_moves(&self, add: &mut impl MoveAdd) {
+        self.add_sudo_moves_by(GenKind::All, !0, add);
+    }
+
+    // captures and promotions only, for quiescence search
+    pub fn add_sudo_captures(&self, add: &mut impl MoveAdd) {
+        self.add_sudo_moves_by(GenKind::Captures, !0, add);
+    }
+
+    // everything add_sudo_captures leaves out
+    pub fn add_sudo_quiets(&self, add: &mut impl MoveAdd) {
+        self.add_sudo_moves_by(GenKind::Quiets, !0, add);
+    }
+
+    // whether movegen could come up with mv here, ignoring check. for moves
+    // from somewhere else, like the hash table or a killer slot
+    pub fn is_pseudo_legal(&self, mv: Move) -> bool {
+        let mut found = false;
+        self.add_sudo_moves_by(GenKind::All, pos_bit(mv.a), &mut |m| found |= m == mv);
+        found
+    }
+
+    // only pieces on the from squares get moves
+    fn add_sudo_moves_by(&self, kind: GenKind, from: BitBoard, add: &mut impl MoveAdd) {
+        let clr = self.turn();
+        let own = self.occupied(clr);
+        let enemy = self.occupied(clr.other());
+        let occ = own | enemy;
+        let targets = match kind {
+            GenKind::All => !own,
+            GenKind::Captures => enemy,
+            GenKind::Quiets => !occ,
+        };
+
+        let capture_at = |pos| self.idx(pos).0.map(|pc| pc.typ);
+        // adds a move from orig to every target bit
+        let mut add_bits = |orig, bits| {
+            for pos in BitIter(bits) {
+                add(Move {
+                    a: orig,
+                    b: pos,
+                    capture: capture_at(pos),
+                    extra: None,
+                });
+            }
+        };
+
+        let pieces = |typ| self.pieces(clr, typ) & from;
+        for orig in BitIter(pieces(Type::Knight)) {
+            add_bits(orig, knight_attacks(orig) & targets);
+        }
+        for orig in BitIter(pieces(Type::Bishop)) {
+            add_bits(orig, bishop_attacks(orig, occ) & targets);
+        }
+        for orig in BitIter(pieces(Type::Rook)) {
+            add_bits(orig, rook_attacks(orig, occ) & targets);
+        }
+        for orig in BitIter(pieces(Type::Queen)) {
+            add_bits(orig, queen_attacks(orig, occ) & targets);
+        }
+        for king in BitIter(pieces(Type::King)) {
+            add_bits(king, king_attacks(king) & targets);
+        }
+
+        self.add_pawn_moves(kind, pieces(Type::Pawn), add);
+        if kind != GenKind::Captures && pieces(Type::King) != 0 {
+            self.add_castle_moves(add);
+        }
+    }
+
+    fn add_pawn_moves(&self, kind: GenKind, pawns: BitBoard, add: &mut impl MoveAdd) {
+        let clr = self.turn();
+        let enemy = self.occupied(clr.other());
+        let occ = self.occupied_all();
+        let dir = pawn_dir(clr);
         let enp = self.get_extra().enp;
-        let mut add_moves = |orig| {
-            let Piece { clr, typ } = match self.get(orig).unwrap() {
-                Sq(Some(pc)) => *pc,
-                Sq(None) => return,
-            };
-            if clr != self.turn() {
-                return;
+
+        for orig in BitIter(pawns) {
+            // move-onlies
+            let mut dsts = 0;
+            let push = orig + dir;
+            if pos_bit(push) & occ == 0 {
+                dsts |= pos_bit(push);
+                let double = push + dir;
+                if orig.y == rel_y(clr, 1) && pos_bit(double) & occ == 0 {
+                    dsts |= pos_bit(double);
+                }
             }
+            // diagonal
+            dsts |= pawn_attacks(clr, orig) & enemy;
 
-            // constructs a helper closure to add a move going from orig -> pos
-            macro_rules! try_move {
-                ($gate: expr, $extra: expr) => {
-                    |pos| {
-                        self.try_move(
-                            $gate,
-                            add,
-                            Move {
-                                a: orig,
-                                b: pos,
-                                capture: None,
-                                extra: $extra,
-                            },
-                        )
-                    }
+            for b in BitIter(dsts) {
+                let mv = Move {
+                    a: orig,
+                    b,
+                    capture: self.idx(b).0.map(|pc| pc.typ),
+                    extra: None,
                 };
-            }
-            macro_rules! pawn_move {
-                ($pos:expr, $is_take:expr) => {
-                    // last row is a promotion move
-                    if orig.y == rel_y(clr.other(), 1) {
-                        for &typ in &[Type::Knight, Type::Bishop, Type::Rook, Type::Queen] {
-                            try_move!(|take| take == $is_take, Some(MvExtra::Promote(typ)))($pos);
-                        }
-                        false
-                    } else {
-                        try_move!(|take| take == $is_take, None)($pos)
+                // last row is a promotion move
+                let promote = b.y == rel_y(clr, BOARD_DIM.y - 1);
+                let noisy = promote || mv.capture.is_some();
+                match (kind, noisy) {
+                    (GenKind::Captures, false) | (GenKind::Quiets, true) => continue,
+                    _ => (),
+                }
+                if promote {
+                    for &typ in &[Type::Knight, Type::Bishop, Type::Rook, Type::Queen] {
+                        add(Move {
+                            extra: Some(MvExtra::Promote(typ)),
+                            ..mv
+                        });
                     }
-                };
-            }
-            macro_rules! add_move {
-                () => {
-                    try_move!(|_| true, None)
-                };
-                ($pos: expr, $extra: expr) => {
-                    try_move!(|_| true, $extra)($pos)
-                };
+                } else {
+                    add(mv);
+                }
             }
 
-            match typ {
-                Type::Pawn => {
-                    let dir = pawn_dir(clr);
-                    // move-onlies
-                    let first_push = pawn_move!(orig + dir, false);
-                    if first_push && orig.y == rel_y(clr, 1) {
-                        pawn_move!(orig + dir * 2, false);
-                    }
-
-                    // diagonal
-                    for &side in &[card::E, card::W] {
-                        let take_pos = orig + dir + side;
-                        // needs to be able to "take" the spot they skipped
-                        let y_match = orig.y == rel_y(clr.other(), 3);
-                        if enp.map_or(false, |e| take_pos.x == e.x) && y_match {
-                            add_move!(take_pos, Some(MvExtra::EnPassant));
-                        } else {
-                            pawn_move!(take_pos, true);
-                        }
-                    }
-                }
-                Type::Knight => leaper(orig, KNIGHT_OPTS, add_move!()),
-                Type::Bishop => rider(orig, BISHOP_OPTS, add_move!()),
-                Type::Rook => rider(orig, ROOK_OPTS, add_move!()),
-                Type::Queen => {
-                    rider(orig, BISHOP_OPTS, add_move!());
-                    rider(orig, ROOK_OPTS, add_move!());
+            // needs to be able to "take" the spot they skipped
+            if let Some(e) = enp.filter(|_| kind != GenKind::Quiets) {
+                if orig.y == rel_y(clr.other(), 3) && (e.x - orig.x).abs() == 1 {
+                    add(Move {
+                        a: orig,
+                        b: Pos { x: e.x, y: push.y },
+                        capture: Some(Type::Pawn),
+                        extra: Some(MvExtra::EnPassant),
+                    });
                 }
-                Type::King => {
-                    leaper(orig, BISHOP_OPTS, add_move!());
-                    leaper(orig, ROOK_OPTS, add_move!());
-                    let mut try_castle_side = |dir, side| {
-                        // must have castle rights
-                        if !*self.get_extra().get_castle(clr, side) {
-                            return;
-                        }
-                        // loop across the rook path, should be clear
-                        let (src, mut dst) = castle_rook_path(clr, side);
-                        while dst != src {
-                            if *self.idx(dst) != Sq(None) {
-                                return;
-                            }
-                            dst += dir;
-                        }
-                        // lastly, can't castle out of/through check
-                        if !self.in_check(clr) && !self.is_attacked(orig + dir, clr.other()) {
-                            add_move!(orig + dir * 2, Some(MvExtra::Castle(side)));
-                        }
-                    };
-                    try_castle_side(card::W, CastleSide::Long);
-                    try_castle_side(card::E, CastleSide::Short);
+            }
+        }
+    }
+
+    fn add_castle_moves(&self, add: &mut impl MoveAdd) {
+        let clr = self.turn();
+        let orig = *self.get_king_pos(clr);
+        let mut try_castle_side = |side| {
+            // must have castle rights
+            if !*self.get_extra().get_castle(clr, side) {
+                return;
+            }
+            // everything the king and rook cross has to be empty, other than
+            // themselves. in chess960 they can start anywhere on the rank
+            let (src, dst) = self.castle_rook_path(clr, side);
+            let king_dst = castle_king_dst(clr, side);
+            let blockers = self.occupied_all() & !pos_bit(orig) & !pos_bit(src);
+            if (rank_span(orig, king_dst) | rank_span(src, dst)) & blockers != 0 {
+                return;
+            }
+            // lastly, can't castle out of/through check. the destination is
+            // checked by is_legal_move once the rook has moved
+            if self.in_check(clr) {
+                return;
+            }
+            let dir = Pos {
+                x: (king_dst.x - orig.x).signum(),
+                y: 0,
+            };
+            let mut pos = orig + dir;
+            while pos != king_dst {
+                if self.is_attacked(pos, clr.other()) {
+                    return;
                 }
+                pos += dir;
             }
+            add(Move {
+                a: orig,
+                // chess960 castling is written as the king taking the rook
+                b: if self.chess960() { src } else { king_dst },
+                capture: None,
+                extra: Some(MvExtra::Castle(side)),
+            });
         };
-        for y in 0..BOARD_DIM.y {
-            for x in 0..BOARD_DIM.x {
-                add_moves(Pos { y, x });
-            }
-        }
+        try_castle_side(CastleSide::Long);
+        try_castle_side(CastleSide::Short);
     }
     // find move with matching to_str
     pub fn find_move(&mut self, mv_str: &str) -> Option<Move> {
@@ -290,27 +281,36 @@ impl State {
     }
     // make matching moves in sequence
     pub fn run_moves<'a>(&mut self, moves_str: impl Iterator<Item = &'a str>) {
+        if let Err(e) = self.try_run_moves(moves_str) {
+            panic!("{}", e);
+        }
+    }
+    // run_moves, but stops at the first move that doesn't match
+    pub fn try_run_moves<'a>(
+        &mut self,
+        moves_str: impl Iterator<Item = &'a str>,
+    ) -> Result<(), ChessParseError> {
         for mv_str in moves_str.filter(|s| !s.is_empty()) {
             match self.find_move(mv_str) {
                 Some(mv) => self.make_move(mv),
-                None => panic!("no matching move {}", mv_str),
+                None => return Err(ChessParseError::Move(mv_str.to_string())),
             };
         }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::{testpos::*, *};
+    use alloc::vec::Vec;
 
     fn test_move(st: Option<&str>, moves_str: &str) -> Option<Move> {
-        let mut state: State = st
-            .and_then(|s| Some(str::parse(s).unwrap()))
-            .unwrap_or_default();
+        let mut state: State = st.map(|s| str::parse(s).unwrap()).unwrap_or_default();
         let mut moves: Vec<&str> = moves_str.split(" ").collect();
         let last = moves.pop().unwrap();
 
-        state.run_moves(moves.iter().map(|&s| s));
+        state.run_moves(moves.iter().copied());
 
         state.find_move(last)
     }
@@ -334,4 +334,44 @@ mod test {
     fn kiwipete_no_castle_take() {
         assert!(test_move(Some(KIWIPETE), "e2a6 b4b3 a6c8 e8c8").is_none());
     }
+    #[test]
+    fn chess960_castle() {
+        let fen = "1r2k1r1/8/8/8/8/8/8/1R2K1R1 w GBgb - 0 1";
+        let mut state: State = str::parse(fen).unwrap();
+        assert!(state.find_move("e1c1").is_some());
+        // king takes rook in chess960 mode
+        state.set_chess960(true);
+        assert!(state.find_move("e1c1").is_none());
+        state.run_moves("e1b1".split(' '));
+        assert_eq!(state.to_string(), "1r2k1r1/8/8/8/8/8/8/2KR2R1 b kq - 1 1");
+        state.unmake_move();
+        assert_eq!(
+            state.to_string(),
+            str::parse::<State>(fen).unwrap().to_string()
+        );
+
+        // the king stays put, the rook hops over it
+        let mut state: State = str::parse("4k3/8/8/8/8/8/8/5RKR w H - 0 1").unwrap();
+        state.set_chess960(true);
+        assert!(state.find_move("g1h1").is_none());
+        let mut state: State = str::parse("4k3/8/8/8/8/8/8/6KR w H - 0 1").unwrap();
+        state.set_chess960(true);
+        state.run_moves("g1h1".split(' '));
+        assert_eq!(state.to_string(), "4k3/8/8/8/8/8/8/5RK1 b - - 1 1");
+    }
+    #[test]
+    fn captures_match_movegen() {
+        for fen in [KIWIPETE, POS_3, POS_4, POS_5, POS_6, DUB_M8] {
+            let state: State = str::parse(fen).unwrap();
+            let expected: Vec<_> = state
+                .gen_sudo_moves()
+                .into_iter()
+                .filter(|mv| mv.capture.is_some() || matches!(mv.extra, Some(MvExtra::Promote(_))))
+                .collect();
+            let mut captures = state.gen_sudo_captures();
+            captures.retain(|mv| !expected.contains(mv));
+            assert!(captures.is_empty());
+            assert_eq!(state.gen_sudo_captures().len(), expected.len());
+        }
+    }
 }
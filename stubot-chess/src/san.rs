@@ -0,0 +1,244 @@
+use super::*;
+
+use alloc::string::*;
+use alloc::vec::Vec;
+
+fn typ_letter(typ: Type) -> char {
+    match typ {
+        Type::Pawn => 'P',
+        Type::Knight => 'N',
+        Type::Bishop => 'B',
+        Type::Rook => 'R',
+        Type::Queen => 'Q',
+        Type::King => 'K',
+    }
+}
+fn letter_typ(c: char) -> Option<Type> {
+    match c.to_ascii_uppercase() {
+        'N' => Some(Type::Knight),
+        'B' => Some(Type::Bishop),
+        'R' => Some(Type::Rook),
+        'Q' => Some(Type::Queen),
+        'K' => Some(Type::King),
+        _ => None,
+    }
+}
+fn promote_typ(mv: Move) -> Option<Type> {
+    match mv.extra {
+        Some(MvExtra::Promote(typ)) => Some(typ),
+        _ => None,
+    }
+}
+
+impl State {
+    fn typ_at(&self, pos: Pos) -> Option<Type> {
+        self.idx(pos).0.map(|pc| pc.typ)
+    }
+
+    // standard algebraic notation for a legal move, e.g. Nbd7, exd6, O-O+
+    pub fn move_to_san(&mut self, mv: Move) -> String {
+        let mut san = match mv.extra {
+            Some(MvExtra::Castle(CastleSide::Short)) => "O-O".to_string(),
+            Some(MvExtra::Castle(CastleSide::Long)) => "O-O-O".to_string(),
+            _ => self.move_to_san_body(mv),
+        };
+
+        self.make_move(mv);
+        if self.in_check(self.turn()) {
+            san.push(if self.gen_moves().is_empty() {
+                '#'
+            } else {
+                '+'
+            });
+        }
+        self.unmake_move();
+        san
+    }
+
+    fn move_to_san_body(&mut self, mv: Move) -> String {
+        let typ = self.typ_at(mv.a).unwrap();
+        let mut san = String::new();
+        if typ == Type::Pawn {
+            if mv.capture.is_some() {
+                san += &mv.a.to_string()[..1];
+            }
+        } else {
+            san.push(typ_letter(typ));
+            // other pieces of the same type that can go to the same square
+            let others: Vec<Pos> = self
+                .gen_moves()
+                .into_iter()
+                .filter(|o| o.b == mv.b && o.a != mv.a && self.typ_at(o.a) == Some(typ))
+                .map(|o| o.a)
+                .collect();
+            let a_str = mv.a.to_string();
+            if !others.is_empty() {
+                if others.iter().all(|o| o.x != mv.a.x) {
+                    san += &a_str[..1];
+                } else if others.iter().all(|o| o.y != mv.a.y) {
+                    san += &a_str[1..];
+                } else {
+                    san += &a_str;
+                }
+            }
+        }
+        if mv.capture.is_some() {
+            san.push('x');
+        }
+        san += &mv.b.to_string();
+        if let Some(promote) = promote_typ(mv) {
+            san.push('=');
+            san.push(typ_letter(promote));
+        }
+        san
+    }
+
+    // reads SAN, also accepting 0-0, lowercase pieces, a missing x/=/+/#,
+    // extra disambiguation and trailing annotations like !?
+    pub fn parse_san(&mut self, san: &str) -> Result<Move, ChessParseError> {
+        let err = || ChessParseError::Move(san.to_string());
+        let trimmed = san.trim().trim_end_matches(['+', '#', '!', '?']);
+
+        let castle = match trimmed.replace('0', "O").to_ascii_uppercase().as_str() {
+            "O-O" => Some(CastleSide::Short),
+            "O-O-O" => Some(CastleSide::Long),
+            _ => None,
+        };
+        let moves = self.gen_moves();
+        if let Some(side) = castle {
+            return moves
+                .into_iter()
+                .find(|mv| mv.extra == Some(MvExtra::Castle(side)))
+                .ok_or_else(err);
+        }
+
+        let mut chars: Vec<char> = trimmed.chars().filter(|c| !"x:-=".contains(*c)).collect();
+        // promotion piece comes after the destination rank
+        let mut promote = None;
+        if chars.len() >= 3 && chars[chars.len() - 2].is_ascii_digit() {
+            promote = Some(letter_typ(chars.pop().unwrap()).ok_or_else(err)?);
+        }
+        if chars.len() < 2 {
+            return Err(err());
+        }
+        let dst: String = chars.split_off(chars.len() - 2).into_iter().collect();
+        let dst: Pos = str::parse(&dst).map_err(|_| err())?;
+
+        // a lowercase b could be a bishop or the b file, so try both
+        let mut readings = Vec::new();
+        match chars.first() {
+            Some(&c) if c.is_ascii_uppercase() || "nrqk".contains(c) => {
+                readings.push((letter_typ(c).ok_or_else(err)?, &chars[1..]));
+            }
+            Some('b') => {
+                readings.push((Type::Bishop, &chars[1..]));
+                readings.push((Type::Pawn, &chars[..]));
+            }
+            _ => readings.push((Type::Pawn, &chars[..])),
+        }
+
+        let mut found: Vec<Move> = Vec::new();
+        for (typ, from) in readings {
+            for &mv in &moves {
+                let from_ok = from.iter().all(|&c| match c {
+                    'a'..='h' => mv.a.x == c as i8 - 'a' as i8,
+                    '1'..='8' => mv.a.y == c as i8 - '1' as i8,
+                    _ => false,
+                });
+                if mv.b == dst
+                    && from_ok
+                    && self.typ_at(mv.a) == Some(typ)
+                    && promote_typ(mv) == promote
+                    && !found.contains(&mv)
+                {
+                    found.push(mv);
+                }
+            }
+        }
+        match found.as_slice() {
+            &[mv] => Ok(mv),
+            _ => Err(err()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{testpos::*, *};
+
+    fn san(fen: &str, mv: &str) -> String {
+        let mut state: State = str::parse(fen).unwrap();
+        let mv = state.find_move(mv).unwrap();
+        state.move_to_san(mv)
+    }
+    fn parse(fen: &str, san: &str) -> Option<String> {
+        let mut state: State = str::parse(fen).unwrap();
+        state.parse_san(san).ok().map(|mv| mv.to_string())
+    }
+
+    const ROOKS: &str = "4k3/8/8/R7/8/8/4K3/R6R w - - 0 1";
+    const QUEENS: &str = "4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1";
+    const PROMOTE: &str = "1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+    const ENP: &str = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1";
+
+    #[test]
+    fn to_san() {
+        let start = &State::default().to_string();
+        assert_eq!(san(start, "e2e4"), "e4");
+        assert_eq!(san(start, "g1f3"), "Nf3");
+        assert_eq!(san(KIWIPETE, "e1g1"), "O-O");
+        assert_eq!(san(KIWIPETE, "e1c1"), "O-O-O");
+        assert_eq!(san(KIWIPETE, "e2a6"), "Bxa6");
+        assert_eq!(san(KIWIPETE, "d5e6"), "dxe6");
+        assert_eq!(san(KIWIPETE, "f3f6"), "Qxf6");
+        assert_eq!(san(ENP, "e5d6"), "exd6");
+        assert_eq!(san(ROOK_MATE_W, "h6h8"), "Rh8#");
+        assert_eq!(san(PROMOTE, "a7a8q"), "a8=Q");
+        assert_eq!(san(PROMOTE, "a7b8n"), "axb8=N");
+        assert_eq!(san(PROMOTE, "a7b8q"), "axb8=Q+");
+    }
+    #[test]
+    fn disambiguation() {
+        assert_eq!(san(ROOKS, "a1d1"), "Rad1");
+        assert_eq!(san(ROOKS, "h1d1"), "Rhd1");
+        assert_eq!(san(ROOKS, "a1a3"), "R1a3");
+        assert_eq!(san(ROOKS, "a5a3"), "R5a3");
+        assert_eq!(san(QUEENS, "a1b2"), "Qa1b2");
+        assert_eq!(san(QUEENS, "c1b2"), "Qcb2");
+        assert_eq!(san(QUEENS, "a3b2"), "Q3b2");
+    }
+    #[test]
+    fn from_san() {
+        let start = &State::default().to_string();
+        assert_eq!(parse(start, "e4").unwrap(), "e2e4");
+        assert_eq!(parse(start, "Nf3").unwrap(), "g1f3");
+        assert_eq!(parse(start, "nf3").unwrap(), "g1f3");
+        assert_eq!(parse(start, "Ngf3").unwrap(), "g1f3");
+        assert_eq!(parse(start, "Nf3!?").unwrap(), "g1f3");
+        assert_eq!(parse(KIWIPETE, "0-0").unwrap(), "e1g1");
+        assert_eq!(parse(KIWIPETE, "O-O-O").unwrap(), "e1c1");
+        assert_eq!(parse(KIWIPETE, "Qxf6").unwrap(), "f3f6");
+        assert_eq!(parse(KIWIPETE, "bxa6").unwrap(), "e2a6");
+        assert_eq!(parse(ENP, "exd6").unwrap(), "e5d6");
+        assert_eq!(parse(ENP, "ed6").unwrap(), "e5d6");
+        assert_eq!(parse(PROMOTE, "a8=Q").unwrap(), "a7a8q");
+        assert_eq!(parse(PROMOTE, "a8q").unwrap(), "a7a8q");
+        assert_eq!(parse(PROMOTE, "axb8N").unwrap(), "a7b8n");
+        assert_eq!(parse(QUEENS, "Qa1b2").unwrap(), "a1b2");
+        // ambiguous or impossible
+        assert_eq!(parse(ROOKS, "Rd1"), None);
+        assert_eq!(parse(QUEENS, "Qab2"), None);
+        assert_eq!(parse(start, "e5"), None);
+        assert_eq!(parse(PROMOTE, "a8"), None);
+    }
+    #[test]
+    fn san_round_trip() {
+        for fen in [KIWIPETE, POS_3, POS_4, POS_5, POS_6, DUB_M8] {
+            let mut state: State = str::parse(fen).unwrap();
+            for mv in state.gen_moves() {
+                let san = state.move_to_san(mv);
+                assert_eq!(state.parse_san(&san), Ok(mv), "{} in {}", san, fen);
+            }
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use super::*;
+
+use core::cmp;
+
+// most pieces that can ever take turns capturing on one square
+const MAX_SWAPS: usize = 32;
+
+fn see_value(typ: Type) -> i16 {
+    TYP_VALS[typ as usize]
+}
+
+impl State {
+    // pieces of both colors attacking pos, with sliders seeing through
+    // everything missing from occ
+    fn attackers(&self, pos: Pos, occ: BitBoard) -> BitBoard {
+        let both = |typ| self.pieces(Color::White, typ) | self.pieces(Color::Black, typ);
+        let queens = both(Type::Queen);
+        // reverse the pawn attack direction, this is relative to the target
+        let pawns = pawn_attacks(Color::Black, pos) & self.pieces(Color::White, Type::Pawn)
+            | pawn_attacks(Color::White, pos) & self.pieces(Color::Black, Type::Pawn);
+        let attackers = pawns
+            | knight_attacks(pos) & both(Type::Knight)
+            | king_attacks(pos) & both(Type::King)
+            | bishop_attacks(pos, occ) & (both(Type::Bishop) | queens)
+            | rook_attacks(pos, occ) & (both(Type::Rook) | queens);
+        attackers & occ
+    }
+    // every piece of clr that attacks pos, including sliders lined up
+    // behind other attackers, which join in once those have moved to pos
+    pub fn attackers_to(&self, pos: Pos, clr: Color) -> BitBoard {
+        let mut occ = self.occupied_all();
+        let mut found = 0;
+        loop {
+            let revealed = self.attackers(pos, occ) & !found;
+            if revealed == 0 {
+                return found & self.occupied(clr);
+            }
+            found |= revealed;
+            occ &= !revealed;
+        }
+    }
+
+    // the cheapest of clr's attackers
+    fn least_valuable(&self, attackers: BitBoard, clr: Color) -> Option<(Type, BitBoard)> {
+        let types = [
+            Type::Pawn,
+            Type::Knight,
+            Type::Bishop,
+            Type::Rook,
+            Type::Queen,
+            Type::King,
+        ];
+        types.iter().find_map(|&typ| {
+            let pcs = attackers & self.pieces(clr, typ);
+            // lowest set bit
+            (pcs != 0).then(|| (typ, pcs & pcs.wrapping_neg()))
+        })
+    }
+
+    // static exchange evaluation: material won by mv once both sides are
+    // done recapturing on its destination, each with their cheapest piece
+    // and free to stop whenever that's better. pins are ignored
+    pub fn see(&self, mv: Move) -> i16 {
+        let clr = match self.idx(mv.a).0 {
+            Some(pc) => pc.clr,
+            None => return 0,
+        };
+        let mut gain = [0; MAX_SWAPS];
+        let mut occ = self.occupied_all() & !pos_bit(mv.a);
+        // value of the piece that would be taken next
+        let mut last = self.idx(mv.a).0.map_or(0, |pc| see_value(pc.typ));
+        match mv.extra {
+            Some(MvExtra::Castle(_)) => return 0,
+            Some(MvExtra::EnPassant) => occ &= !pos_bit(en_passant_cap(mv)),
+            Some(MvExtra::Promote(typ)) => {
+                gain[0] = see_value(typ) - see_value(Type::Pawn);
+                last = see_value(typ);
+            }
+            None => (),
+        }
+        gain[0] += mv.capture.map_or(0, see_value);
+        // recaptures land on the last rank
+        let promotes = mv.b.y == 0 || mv.b.y == BOARD_DIM.y - 1;
+
+        let mut side = clr.other();
+        let mut d = 0;
+        while d + 1 < MAX_SWAPS {
+            let attackers = self.attackers(mv.b, occ);
+            let (typ, from) = match self.least_valuable(attackers, side) {
+                Some(found) => found,
+                None => break,
+            };
+            // the king can't take if anything would take it back
+            if typ == Type::King && attackers & self.occupied(side.other()) != 0 {
+                break;
+            }
+            d += 1;
+            gain[d] = last - gain[d - 1];
+            last = see_value(typ);
+            if typ == Type::Pawn && promotes {
+                gain[d] += see_value(Type::Queen) - see_value(Type::Pawn);
+                last = see_value(Type::Queen);
+            }
+            occ &= !from;
+            side = side.other();
+        }
+        // each side only recaptures if it gains them something
+        while d > 0 {
+            gain[d - 1] = -cmp::max(-gain[d - 1], gain[d]);
+            d -= 1;
+        }
+        gain[0]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn see(fen: &str, mv: &str) -> i16 {
+        let mut state: State = str::parse(fen).unwrap();
+        let mv = state.find_move(mv).unwrap();
+        state.see(mv)
+    }
+    fn p(s: &str) -> Pos {
+        str::parse(s).unwrap()
+    }
+
+    #[test]
+    fn attackers() {
+        // rook battery on the e file, the rear one through the front one
+        let state: State = str::parse("4k3/4r3/8/4p3/8/8/4R3/4R2K w - - 0 1").unwrap();
+        let white = state.attackers_to(p("e5"), Color::White);
+        assert_eq!(white, pos_bit(p("e2")) | pos_bit(p("e1")));
+        assert_eq!(state.attackers_to(p("e5"), Color::Black), pos_bit(p("e7")));
+        // and through an enemy piece that gets there first
+        let state: State = str::parse("4k3/8/8/8/8/8/r7/R3K3 w - - 0 1").unwrap();
+        assert_eq!(state.attackers_to(p("a3"), Color::White), pos_bit(p("a1")));
+        assert_eq!(state.attackers_to(p("a3"), Color::Black), pos_bit(p("a2")));
+        // two pawns, the knight, and the queen behind the e pawn
+        let state = State::default();
+        assert_eq!(state.attackers_to(p("f3"), Color::White).count_ones(), 4);
+        assert_eq!(state.attackers_to(p("e4"), Color::White), 0);
+    }
+    #[test]
+    fn exchanges() {
+        // free pawn
+        assert_eq!(see("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1", "e4d5"), 100);
+        // defended pawn
+        assert_eq!(see("4k3/8/2p5/3p4/8/8/8/3RK3 w - - 0 1", "d1d5"), -400);
+        // the back rook wins the exchange
+        assert_eq!(see("4k3/4r3/8/4p3/8/8/4R3/4R2K w - - 0 1", "e2e5"), 100);
+        // black would lose the rook for the knight, so it doesn't recapture
+        assert_eq!(see("4k3/4r3/8/4p3/8/5N2/8/4R2K w - - 0 1", "f3e5"), 100);
+        // knight takes, trades down, and ends a piece short
+        assert_eq!(
+            see(
+                "1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1",
+                "d3e5"
+            ),
+            -220
+        );
+        // a quiet move into an attacked square hangs the piece
+        assert_eq!(see("4k3/8/8/2p5/8/8/8/1R2K3 w - - 0 1", "b1b4"), -500);
+        assert_eq!(see("4k3/8/8/8/8/8/8/1R2K3 w - - 0 1", "b1b4"), 0);
+    }
+    #[test]
+    fn special_moves() {
+        assert_eq!(see("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2", "e5d6"), 100);
+        // the en passant pawn gets taken back
+        assert_eq!(see("4k3/2p5/8/3pP3/8/8/8/4K3 w - d6 0 2", "e5d6"), 0);
+        assert_eq!(see("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8q"), 800);
+        assert_eq!(see("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8n"), 220);
+        // promotes taking the rook, the king takes the queen back
+        assert_eq!(see("rk6/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7a8q"), 400);
+        // a recapturing pawn promotes too
+        assert_eq!(see("1N5r/P3k3/8/8/8/8/8/4K3 b - - 0 1", "h8b8"), -980);
+        assert_eq!(see("4k3/8/8/8/8/8/8/4K2R w K - 0 1", "e1g1"), 0);
+    }
+    #[test]
+    fn king_recaptures() {
+        // the king can take back the bishop
+        assert_eq!(see("4k3/8/8/8/8/2b5/3p4/3RK3 w - - 0 1", "d1d2"), -70);
+        // but not with the rook behind it
+        assert_eq!(see("3rk3/8/8/8/8/2b5/3p4/3RK3 w - - 0 1", "d1d2"), -400);
+    }
+}
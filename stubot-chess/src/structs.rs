@@ -119,22 +119,61 @@ impl Sq {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct ChessParseError {
-    pub name: String,
-}
-
-impl ChessParseError {
-    pub fn new(name: &str) -> Self {
-        ChessParseError {
-            name: name.to_string(),
-        }
-    }
+// what went wrong reading a piece, square, move or FEN. the FEN field
+// variants carry the offending text
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChessParseError {
+    Piece(String),
+    Pos(String),
+    // not a legal move in the position
+    Move(String),
+    // FEN needs 6 space separated fields
+    FieldCount(usize),
+    // the piece placement needs 8 ranks
+    RankCount(usize),
+    // a rank (counting from 1) doesn't add up to 8 squares
+    RankLength(i8),
+    Turn(String),
+    Castle(String),
+    EnPassant(String),
+    HalfMove(String),
+    FullMove(String),
+    // the rest are from State::validate, the fields parsed but the
+    // position can't happen in a game
+    KingCount(Color, u32),
+    PawnOnBackRank(Pos),
+    OpponentInCheck,
+    CastleRights(Color, CastleSide),
 }
 
 impl fmt::Display for ChessParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "parse {} error", self.name)
+        use ChessParseError::*;
+        match self {
+            Piece(s) => write!(f, "invalid piece '{}'", s),
+            Pos(s) => write!(f, "invalid square '{}'", s),
+            Move(s) => write!(f, "no matching move '{}'", s),
+            FieldCount(n) => write!(f, "FEN has {} fields, expected 6", n),
+            RankCount(n) => write!(f, "FEN board has {} ranks, expected 8", n),
+            RankLength(rank) => write!(f, "FEN rank {} doesn't have 8 squares", rank),
+            Turn(s) => write!(f, "invalid side to move '{}'", s),
+            Castle(s) => write!(f, "invalid castling rights '{}'", s),
+            EnPassant(s) => write!(f, "invalid en passant square '{}'", s),
+            HalfMove(s) => write!(f, "invalid halfmove clock '{}'", s),
+            FullMove(s) => write!(f, "invalid fullmove number '{}'", s),
+            KingCount(clr, n) => write!(f, "{} has {} kings, expected 1", clr, n),
+            PawnOnBackRank(pos) => write!(f, "pawn on back rank at {}", pos),
+            OpponentInCheck => write!(f, "side not to move is in check"),
+            CastleRights(clr, side) => write!(
+                f,
+                "{} can't castle {} without king and rook in place",
+                clr,
+                match side {
+                    CastleSide::Long => "long",
+                    CastleSide::Short => "short",
+                }
+            ),
+        }
     }
 }
 
@@ -198,7 +237,7 @@ impl str::FromStr for Sq {
                 }
             }
         }
-        Err(ChessParseError::new("Sq"))
+        Err(ChessParseError::Piece(sq_str.to_string()))
     }
 }
 
@@ -213,16 +252,14 @@ impl str::FromStr for Pos {
     type Err = ChessParseError;
 
     fn from_str(pos: &str) -> Result<Self, Self::Err> {
-        if pos.len() < 2 {
-            return Err(ChessParseError::new("Pos"));
+        // checked before subtracting, a non-ascii byte would overflow an i8
+        match *pos.as_bytes() {
+            [file @ b'a'..=b'h', rank @ b'1'..=b'8'] => Ok(Pos {
+                x: (file - b'a') as i8,
+                y: (rank - b'1') as i8,
+            }),
+            _ => Err(ChessParseError::Pos(pos.to_string())),
         }
-
-        let x = pos.chars().nth(0).unwrap() as i8 - 'a' as i8;
-        let y: i8 = match str::parse(&pos[1..]).ok() {
-            Some(v) => Ok(v),
-            None => Err(ChessParseError::new("Pos")),
-        }?;
-        Ok(Pos { x, y: y - 1 })
     }
 }
 
@@ -253,6 +290,16 @@ mod test {
         assert_eq!("c7", Pos { x: 2, y: 6 }.to_string());
     }
 
+    #[test]
+    fn pos_invalid() {
+        for s in ["z9", "a9", "i1", "a0", "a10", "a", "", "é", "ÿ", "aé", "é1"] {
+            assert_eq!(
+                str::parse::<Pos>(s).err(),
+                Some(ChessParseError::Pos(s.to_string()))
+            );
+        }
+    }
+
     #[test]
     fn pos_color() {
         let clr = |s| str::parse::<Pos>(s).unwrap().color();
@@ -0,0 +1,184 @@
+use super::*;
+
+use alloc::string::*;
+use alloc::vec::Vec;
+use core::fmt;
+
+// one line of a trace, both sides' (middlegame, endgame) scores from
+// their own side
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TraceTerm {
+    pub name: &'static str,
+    pub white: (i16, i16),
+    pub black: (i16, i16),
+}
+
+// State::eval taken apart. the terms' white minus black, tapered by
+// phase, add up to total
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalTrace {
+    pub terms: Vec<TraceTerm>,
+    pub phase: i32,
+    pub max_phase: i32,
+    // what eval gives, from white's side
+    pub total: i16,
+}
+
+const TYPES: [Type; 6] = [
+    Type::Pawn,
+    Type::Knight,
+    Type::Bishop,
+    Type::Rook,
+    Type::Queen,
+    Type::King,
+];
+const PST_NAMES: [&str; 6] = [
+    "pawn_pst",
+    "knight_pst",
+    "bishop_pst",
+    "rook_pst",
+    "queen_pst",
+    "king_pst",
+];
+
+impl State {
+    // every term of eval for both sides, without a pawn table
+    pub fn eval_trace(&self) -> EvalTrace {
+        let params = self.eval_params();
+        let mut terms = Vec::new();
+        let mut term = |name, side: &dyn Fn(Color) -> Pair| {
+            terms.push(TraceTerm {
+                name,
+                white: side(Color::White),
+                black: side(Color::Black),
+            })
+        };
+        term("material", &|clr| {
+            let value = TYPES
+                .iter()
+                .map(|&typ| {
+                    self.pieces(clr, typ).count_ones() as i16 * params.piece_values[typ as usize]
+                })
+                .sum();
+            (value, value)
+        });
+        for (&typ, &name) in TYPES.iter().zip(&PST_NAMES) {
+            let (early_tbl, late_tbl) = (
+                &params.pst_early[typ as usize],
+                &params.pst_late[typ as usize],
+            );
+            term(name, &|clr| {
+                let pc = Piece { clr, typ };
+                BitIter(self.pieces(clr, typ)).fold((0, 0), |acc, pos| {
+                    let pst = (tbl_value(early_tbl, pc, pos), tbl_value(late_tbl, pc, pos));
+                    add(acc, pst)
+                })
+            });
+        }
+        term("pawns", &|clr| {
+            let (structure, passed) = self.pawn_side(clr);
+            add(structure, (0, self.passer_race(clr, passed)))
+        });
+        term("mobility", &|clr| self.activity(clr).0);
+        term("king_safety", &|clr| {
+            let (_, danger) = self.activity(clr.other());
+            (self.king_shelter(clr) - danger, 0)
+        });
+        term("pieces", &|clr| self.piece_bonuses(clr));
+
+        let sum = terms
+            .iter()
+            .fold((0, 0), |acc, t| add(acc, sub(t.white, t.black)));
+        let phase = self.phase();
+        EvalTrace {
+            terms,
+            phase,
+            max_phase: MAX_PHASE,
+            total: taper(sum.0, sum.1, phase),
+        }
+    }
+}
+
+impl EvalTrace {
+    pub fn to_json(&self) -> String {
+        let side = |(mg, eg): (i16, i16)| format!("{{\"mg\":{},\"eg\":{}}}", mg, eg);
+        let terms: Vec<_> = self
+            .terms
+            .iter()
+            .map(|t| {
+                format!(
+                    "{{\"name\":\"{}\",\"white\":{},\"black\":{}}}",
+                    t.name,
+                    side(t.white),
+                    side(t.black)
+                )
+            })
+            .collect();
+        format!(
+            "{{\"terms\":[{}],\"phase\":{},\"max_phase\":{},\"total\":{}}}",
+            terms.join(","),
+            self.phase,
+            self.max_phase,
+            self.total
+        )
+    }
+}
+// a table for the uci eval command
+impl fmt::Display for EvalTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<12} {:>8} {:>8} {:>8} {:>8}",
+            "term", "white mg", "white eg", "black mg", "black eg"
+        )?;
+        for t in &self.terms {
+            writeln!(
+                f,
+                "{:<12} {:>8} {:>8} {:>8} {:>8}",
+                t.name, t.white.0, t.white.1, t.black.0, t.black.1
+            )?;
+        }
+        write!(
+            f,
+            "phase {}/{}, total {} from white's side",
+            self.phase, self.max_phase, self.total
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn matches_eval() {
+        for fen in [
+            testpos::YOUR_MOVE,
+            testpos::KIWIPETE,
+            testpos::POS_3,
+            testpos::POS_4,
+            testpos::POS_5,
+            testpos::POS_6,
+            testpos::DUB_M8,
+            testpos::HORIZON_QUEEN,
+        ] {
+            let state: State = str::parse(fen).unwrap();
+            let trace = state.eval_trace();
+            assert_eq!(trace.total, state.eval(&mut PawnTable::new(1)));
+        }
+    }
+    #[test]
+    fn start_trace() {
+        let trace = State::default().eval_trace();
+        assert_eq!((trace.phase, trace.total), (MAX_PHASE, 0));
+        let material = trace.terms[0];
+        assert_eq!(material.name, "material");
+        assert_eq!(material.white, (4000, 4000));
+        assert_eq!(material.white, material.black);
+        let json = trace.to_json();
+        assert!(json.starts_with("{\"terms\":[{\"name\":\"material\",\"white\":{\"mg\":4000"));
+        assert!(json.ends_with("\"phase\":24,\"max_phase\":24,\"total\":0}"));
+        let table = trace.to_string();
+        assert_eq!(table.lines().count(), trace.terms.len() + 2);
+    }
+}
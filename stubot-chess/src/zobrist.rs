@@ -0,0 +1,93 @@
+use super::*;
+
+// random keys for every (piece, square), castle right, en passant file and
+// the side to move. generated at compile time so keys are stable across runs
+pub struct ZobristKeys {
+    pieces: [[[u64; 64]; 6]; 2],
+    castle: [[u64; 2]; 2],
+    enp: [u64; BOARD_DIM.x as usize],
+    turn: u64,
+}
+
+// splitmix64, returns (next state, output)
+const fn splitmix(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (state, z ^ (z >> 31))
+}
+
+const fn gen_keys() -> ZobristKeys {
+    let mut keys = ZobristKeys {
+        pieces: [[[0; 64]; 6]; 2],
+        castle: [[0; 2]; 2],
+        enp: [0; BOARD_DIM.x as usize],
+        turn: 0,
+    };
+    let mut seed = 0x5eed_f00d_57ab_0075;
+    // no for loops in const fn
+    let mut i = 0;
+    while i < 2 * 6 * 64 {
+        let (s, k) = splitmix(seed);
+        seed = s;
+        keys.pieces[i / (6 * 64)][i / 64 % 6][i % 64] = k;
+        i += 1;
+    }
+    i = 0;
+    while i < 4 {
+        let (s, k) = splitmix(seed);
+        seed = s;
+        keys.castle[i / 2][i % 2] = k;
+        i += 1;
+    }
+    i = 0;
+    while i < BOARD_DIM.x as usize {
+        let (s, k) = splitmix(seed);
+        seed = s;
+        keys.enp[i] = k;
+        i += 1;
+    }
+    keys.turn = splitmix(seed).1;
+    keys
+}
+
+static KEYS: ZobristKeys = gen_keys();
+
+pub fn sq_key(sq: Sq, pos: Pos) -> u64 {
+    match sq {
+        Sq(Some(Piece { clr, typ })) => {
+            KEYS.pieces[clr as usize][typ as usize][(pos.y * BOARD_DIM.x + pos.x) as usize]
+        }
+        Sq(None) => 0,
+    }
+}
+pub fn castle_key(clr: Color, side: CastleSide) -> u64 {
+    KEYS.castle[clr as usize][side as usize]
+}
+// only the file matters, the rank is implied by the side to move
+pub fn enp_key(enp: Option<Pos>) -> u64 {
+    enp.map_or(0, |e| KEYS.enp[e.x as usize])
+}
+pub fn turn_key(clr: Color) -> u64 {
+    match clr {
+        Color::White => 0,
+        Color::Black => KEYS.turn,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keys_unique() {
+        let mut all = vec![KEYS.turn];
+        all.extend(KEYS.pieces.iter().flatten().flatten());
+        all.extend(KEYS.castle.iter().flatten());
+        all.extend(KEYS.enp.iter());
+        all.sort_unstable();
+        all.dedup();
+        assert_eq!(all.len(), 1 + 2 * 6 * 64 + 4 + 8);
+    }
+}
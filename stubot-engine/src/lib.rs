@@ -4,10 +4,18 @@ extern crate std;
 #[macro_use]
 extern crate alloc;
 
+mod limits;
+mod ordering;
 mod searcher;
 mod signal;
+#[cfg(all(feature = "std", target_has_atomic = "64"))]
+mod smp;
 mod structs;
+mod table;
 
+pub use limits::*;
+pub use ordering::*;
 pub use searcher::*;
 pub use signal::*;
 pub use structs::*;
+pub use table::*;
@@ -0,0 +1,209 @@
+use chess::{mate_ply, Move};
+
+use core::cell::Cell;
+use core::cmp;
+use core::time::Duration;
+
+// assumed moves left in the game when the gui doesn't send movestogo
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+// hard limit as a multiple of the soft limit
+const HARD_MUL: u32 = 4;
+// the next iteration takes at least this many times longer than all the
+// previous ones put together, so don't start one past soft / EST_BRANCHING
+const EST_BRANCHING: u32 = 2;
+// a score this much lower than the last iteration's means trouble
+const SCORE_DROP: i16 = 30;
+
+// what a single search is allowed to do, besides time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SearchLimits {
+    pub depth: i32,
+    pub nodes: Option<u128>,
+    // stop once a mate in this many moves is found
+    pub mate: Option<u32>,
+}
+
+impl SearchLimits {
+    pub fn depth(depth: i32) -> Self {
+        SearchLimits {
+            depth,
+            nodes: None,
+            mate: None,
+        }
+    }
+    // score is good enough to stop searching
+    pub fn found_mate(&self, score: i16) -> bool {
+        self.mate.is_some_and(|moves| {
+            let ply = cmp::min(moves.saturating_mul(2), u8::MAX as u32) as u8;
+            score >= mate_ply(ply.saturating_sub(1))
+        })
+    }
+}
+
+// clock state from a go command, for the side to move
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TimeControl {
+    pub time: Option<Duration>,
+    pub inc: Duration,
+    pub moves_to_go: Option<u32>,
+    pub move_time: Option<Duration>,
+    // time lost talking to the gui, kept in reserve
+    pub overhead: Duration,
+}
+
+impl TimeControl {
+    // (soft, hard), or None if the search isn't timed. a new iteration
+    // isn't started past the soft limit, and the search is cut at the hard one
+    pub fn limits(&self) -> Option<(Duration, Duration)> {
+        if let Some(move_time) = self.move_time {
+            let limit = move_time
+                .checked_sub(self.overhead)
+                .unwrap_or(move_time / 2);
+            return Some((limit, limit));
+        }
+        let usable = self.time?.saturating_sub(self.overhead);
+        // never plan to spend most of the clock on a single move
+        let cap = usable * 4 / 5;
+        let moves_to_go = self.moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+        let soft = cmp::min(usable / moves_to_go + self.inc * 3 / 4, cap);
+        let hard = cmp::min(soft * HARD_MUL, cap);
+        Some((soft, hard))
+    }
+}
+
+// decides between iterations whether to keep going, giving more time when
+// the best move keeps changing or the score falls
+#[derive(Debug)]
+pub struct TimeManager {
+    soft: Duration,
+    hard: Duration,
+    // soft limit is multiplied by (100 + extend) / 100
+    extend: Cell<u32>,
+    last: Cell<Option<(Move, i16)>>,
+}
+
+impl TimeManager {
+    pub fn new(control: &TimeControl) -> Self {
+        let (soft, hard) = control.limits().unwrap_or((Duration::MAX, Duration::MAX));
+        TimeManager {
+            soft,
+            hard,
+            extend: Cell::new(0),
+            last: Cell::new(None),
+        }
+    }
+    pub fn infinite() -> Self {
+        TimeManager::new(&Default::default())
+    }
+    pub fn hard(&self) -> Duration {
+        self.hard
+    }
+    // soft limit with extensions, never past the hard limit
+    pub fn soft(&self) -> Duration {
+        let extended = self.soft.saturating_mul(100 + self.extend.get()) / 100;
+        cmp::min(extended, self.hard)
+    }
+
+    // called after each finished iteration with its result
+    pub fn next_iteration(&self, elapsed: Duration, mv: Option<Move>, score: i16) -> bool {
+        if let (Some(mv), Some((last_mv, last_score))) = (mv, self.last.get()) {
+            // extensions wear off while the search is stable
+            let mut extend = self.extend.get() / 2;
+            if mv != last_mv {
+                extend += 60;
+            }
+            if score < last_score - SCORE_DROP {
+                extend += 80;
+            }
+            self.extend.set(cmp::min(extend, 200));
+        }
+        if let Some(mv) = mv {
+            self.last.set(Some((mv, score)));
+        }
+        elapsed.saturating_mul(EST_BRANCHING) < self.soft()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec::Vec;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn time_limits() {
+        assert_eq!(TimeControl::default().limits(), None);
+        let blitz = TimeControl {
+            time: Some(ms(180_000)),
+            inc: ms(2000),
+            overhead: ms(30),
+            ..Default::default()
+        };
+        let (soft, hard) = blitz.limits().unwrap();
+        assert!(soft > ms(5000) && soft < ms(10_000));
+        assert_eq!(hard, soft * HARD_MUL);
+
+        // last move before the time control, don't flag
+        let (soft, hard) = TimeControl {
+            moves_to_go: Some(1),
+            ..blitz
+        }
+        .limits()
+        .unwrap();
+        assert!(soft <= hard && hard < ms(180_000 - 30));
+        assert!(soft > ms(100_000));
+
+        // nearly flagged with a big increment
+        let (soft, hard) = TimeControl {
+            time: Some(ms(500)),
+            ..blitz
+        }
+        .limits()
+        .unwrap();
+        assert!(soft <= hard && hard < ms(500));
+
+        let fixed = TimeControl {
+            move_time: Some(ms(1000)),
+            overhead: ms(30),
+            ..Default::default()
+        };
+        assert_eq!(fixed.limits(), Some((ms(970), ms(970))));
+    }
+    #[test]
+    fn instability() {
+        let control = TimeControl {
+            time: Some(ms(60_000)),
+            ..Default::default()
+        };
+        let mvs: Vec<Move> = chess::State::default().gen_moves().to_vec();
+        let stable = TimeManager::new(&control);
+        let shaky = TimeManager::new(&control);
+        for i in 0..4 {
+            stable.next_iteration(ms(10), Some(mvs[0]), 20);
+            shaky.next_iteration(ms(10), Some(mvs[i]), 20 - i as i16 * 50);
+        }
+        assert_eq!(stable.soft(), control.limits().unwrap().0);
+        assert!(shaky.soft() > stable.soft());
+        assert!(shaky.soft() <= shaky.hard());
+
+        // an iteration that took half the soft limit won't be followed
+        let half = stable.soft() / 2;
+        assert!(stable.next_iteration(half - ms(1), Some(mvs[0]), 20));
+        assert!(!stable.next_iteration(half + ms(1), Some(mvs[0]), 20));
+    }
+    #[test]
+    fn mate_limit() {
+        let limits = SearchLimits {
+            mate: Some(2),
+            ..SearchLimits::depth(10)
+        };
+        assert!(limits.found_mate(mate_ply(3)));
+        assert!(limits.found_mate(mate_ply(1)));
+        assert!(!limits.found_mate(mate_ply(5)));
+        assert!(!limits.found_mate(-mate_ply(1)));
+        assert!(!SearchLimits::depth(10).found_mate(mate_ply(1)));
+    }
+}
@@ -0,0 +1,230 @@
+use chess::{is_null, is_quiet, Move, MovePicker, MvExtra, State, Type};
+
+use alloc::vec::Vec;
+
+// check extensions stop this many plies deep
+pub const MAX_PLY: usize = 64;
+// history scores saturate at HISTORY_MAX, well inside an i16
+const HISTORY_MAX: i32 = 1 << 13;
+// the small feature is for the mcu's heap: history by the moved piece's
+// type instead of its square, counters by destination alone and fewer
+// plies of killers. about 1.4KB instead of 11.8KB
+const SMALL: bool = cfg!(feature = "small");
+const KILLER_PLIES: usize = if SMALL { 16 } else { MAX_PLY };
+const HISTORY_ROWS: usize = if SMALL { 6 } else { 64 };
+const COUNTER_ROWS: usize = if SMALL { 1 } else { 6 };
+
+fn sq(pos: chess::Pos) -> usize {
+    (pos.y * 8 + pos.x) as usize
+}
+
+// most valuable victim, least valuable attacker. higher is better
+pub fn mvv_lva(state: &State, mv: Move) -> i16 {
+    let victim = match (mv.capture, mv.extra) {
+        (Some(typ), _) => typ as i16,
+        (None, Some(MvExtra::Promote(_))) => Type::Queen as i16,
+        _ => 0,
+    };
+    let attacker = state.idx(mv.a).0.map_or(0, |pc| pc.typ as i16);
+    victim * 8 - attacker
+}
+
+// what the search learned about good moves, used to try them first
+pub struct MoveOrder {
+    // two quiet moves per ply that caused a beta cutoff
+    killers: Vec<[Option<Move>; 2]>,
+    // butterfly table, how often a quiet move from one square to another
+    // caused a cutoff, weighted by depth. by piece type in small builds
+    history: Vec<i16>,
+    // the quiet move that refuted each move, by the moved piece's type and
+    // destination
+    counters: Vec<Option<Move>>,
+    // order the way the search did before history and counter moves, so
+    // tests can compare the two
+    #[cfg(test)]
+    pub(crate) naive: bool,
+}
+
+impl Default for MoveOrder {
+    fn default() -> Self {
+        MoveOrder {
+            killers: vec![[None; 2]; KILLER_PLIES],
+            history: vec![0; HISTORY_ROWS * 64],
+            counters: vec![None; COUNTER_ROWS * 64],
+            #[cfg(test)]
+            naive: false,
+        }
+    }
+}
+
+impl MoveOrder {
+    // killers are position specific, history just fades between searches
+    pub fn new_search(&mut self) {
+        for killers in self.killers.iter_mut() {
+            *killers = [None; 2];
+        }
+        for h in self.history.iter_mut() {
+            *h /= 2;
+        }
+    }
+
+    fn counter_idx(state: &State) -> Option<usize> {
+        let &prev = state.moves().last().filter(|&&mv| !is_null(mv))?;
+        let pc = state.idx(prev.b).0?;
+        let row = if SMALL { 0 } else { pc.typ as usize };
+        Some(row * 64 + sq(prev.b))
+    }
+    fn history_idx(state: &State, mv: Move) -> usize {
+        let row = match SMALL {
+            true => state.idx(mv.a).0.map_or(0, |pc| pc.typ as usize),
+            false => sq(mv.a),
+        };
+        row * 64 + sq(mv.b)
+    }
+
+    // hash move, captures, killers and the counter move, then quiets
+    pub fn picker(&self, state: &State, hash_mv: Option<Move>, ply: i32) -> MovePicker {
+        let killers = self.killers.get(ply as usize).copied();
+        let counter = MoveOrder::counter_idx(state).and_then(|i| self.counters[i]);
+        #[cfg(test)]
+        let counter = counter.filter(|_| !self.naive);
+        MovePicker::new(hash_mv, killers.unwrap_or_default(), counter)
+    }
+    // captures by what they take, quiets by history. the piece-square gain
+    // breaks ties while history knows nothing
+    pub fn score(&self, state: &State, mv: Move) -> i16 {
+        if !is_quiet(mv) {
+            return mvv_lva(state, mv);
+        }
+        #[cfg(test)]
+        if self.naive {
+            return test::naive_score(state, mv);
+        }
+        let gain = state.idx(mv.a).0.map_or(0, |pc| {
+            state.pst_value(pc, mv.b) - state.pst_value(pc, mv.a)
+        });
+        self.history[MoveOrder::history_idx(state, mv)] + gain
+    }
+
+    // mv caused a beta cutoff at ply with depth left to search, after the
+    // quiets in tried didn't
+    pub fn cutoff<'a>(
+        &mut self,
+        state: &State,
+        ply: i32,
+        depth: i32,
+        mv: Move,
+        tried: impl IntoIterator<Item = &'a Move>,
+    ) {
+        if !is_quiet(mv) {
+            return;
+        }
+        if let Some(killers) = self.killers.get_mut(ply as usize) {
+            if killers[0] != Some(mv) {
+                killers[1] = killers[0];
+                killers[0] = Some(mv);
+            }
+        }
+        #[cfg(test)]
+        if self.naive {
+            return;
+        }
+        if let Some(i) = MoveOrder::counter_idx(state) {
+            self.counters[i] = Some(mv);
+        }
+        // deeper cutoffs count for more, and scores saturate near the max
+        let bonus = (depth * depth).min(HISTORY_MAX);
+        self.update_history(state, mv, bonus);
+        for &other in tried {
+            self.update_history(state, other, -bonus);
+        }
+    }
+    fn update_history(&mut self, state: &State, mv: Move, bonus: i32) {
+        let h = &mut self.history[MoveOrder::history_idx(state, mv)];
+        *h += (bonus - *h as i32 * bonus.abs() / HISTORY_MAX) as i16;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{BlockSignal, Searcher};
+    use chess::testpos::*;
+
+    // quiets by a shallow eval once they're made, what history and counter
+    // moves replaced
+    pub(super) fn naive_score(state: &State, mv: Move) -> i16 {
+        let mut state = state.clone();
+        state.make_move(mv);
+        // it's the enemy's turn now
+        -state.rel_neg(state.fast_score())
+    }
+
+    // better ordering cuts off sooner, so the same searches have to take
+    // fewer nodes than with the old one
+    #[test]
+    fn ordering_nodes() {
+        let nodes = |fen: &str, naive| {
+            let mut searcher = Searcher::new(1 << 16);
+            searcher.order.naive = naive;
+            searcher.iter_negamax(&mut str::parse(fen).unwrap(), 6, &BlockSignal {});
+            searcher.nodes
+        };
+        let (mut old, mut new) = (0, 0);
+        for fen in [
+            YOUR_MOVE,
+            KIWIPETE,
+            POS_3,
+            POS_4,
+            POS_5,
+            POS_6,
+            DUB_M8,
+            HORIZON_QUEEN,
+            MATE_2_B,
+        ] {
+            old += nodes(fen, true);
+            new += nodes(fen, false);
+        }
+        assert!(new < old, "{} nodes, {} with the old ordering", new, old);
+    }
+    #[test]
+    fn history_and_counters() {
+        let mut state: State = str::parse(KIWIPETE).unwrap();
+        let mut order = MoveOrder::default();
+        let quiets: Vec<_> = state
+            .gen_moves()
+            .into_iter()
+            .filter(|&mv| is_quiet(mv))
+            .collect();
+        let (a, b) = (quiets[0], quiets[1]);
+        let before = order.score(&state, a);
+        order.cutoff(&state, 0, 3, a, &[]);
+        assert!(order.score(&state, a) > before);
+        // a was tried first and failed this time
+        order.cutoff(&state, 0, 5, b, &[a]);
+        assert!(order.score(&state, b) > order.score(&state, a));
+        assert!(order.score(&state, a) < before);
+        // saturates instead of overflowing
+        for _ in 0..10_000 {
+            order.cutoff(&state, 0, 60, a, &[b]);
+        }
+        assert!(order.score(&state, a) as i32 <= HISTORY_MAX);
+        order.new_search();
+        assert!(order.killers[0][0].is_none());
+
+        // reply refutes a, so it comes before any other quiet
+        state.make_move(quiets[0]);
+        let reply = state
+            .gen_moves()
+            .into_iter()
+            .find(|&mv| is_quiet(mv))
+            .unwrap();
+        order.cutoff(&state, 1, 2, reply, &[]);
+        let mut picker = order.picker(&state, None, 3);
+        let mut buf = Default::default();
+        let first_quiet = (0..)
+            .map(|_| picker.next(&mut state, &mut buf, |_, _| 0))
+            .find(|mv| mv.is_none_or(is_quiet));
+        assert_eq!(first_quiet, Some(Some(reply)));
+    }
+}
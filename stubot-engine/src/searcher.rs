@@ -1,17 +1,81 @@
 use super::*;
 
-use chess::{State, MATE_BOUND};
+use chess::{
+    is_null, is_quiet, Color, Move, MovePicker, PawnTable, PickerBuf, Stage, State, Type,
+    CHECKMATE, DRAW, MATE_BOUND,
+};
 
 use core::cmp;
 
 #[cfg(test)]
 use alloc::string::*;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 
 // NEGAMAX_HACK misses checkmates at depth 0, but is way faster
 const NEGAMAX_HACK: bool = true;
+// most quiet moves per node that get a history penalty
+const TRIED_QUIETS: usize = 32;
+// null move searches this much shallower, on top of the skipped move
+const NULL_MOVE_R: i32 = 2;
+const NULL_MOVE_DEPTH: i32 = 3;
+// reverse futility: this much over beta per ply left, the node fails high
+const RFP_MARGIN: i16 = 120;
+const RFP_DEPTH: i32 = 3;
+// futility: quiets this far below alpha per ply left aren't searched
+const FUTILITY_MARGIN: i16 = 150;
+const FUTILITY_DEPTH: i32 = 2;
+// late quiets off the pv are searched shallower, unless they beat alpha
+const LMR_MOVES: u32 = 3;
+const LMR_DEPTH: i32 = 3;
+// pawn table entries are an eighth of the transposition table's, which
+// keeps small targets small. past the cap nearly every lookup hits anyway
+const PAWN_TABLE_MAX: usize = 1 << 10;
+
+// zugzwang is common with only pawns left, passing would look best there
+fn has_pieces(state: &State, clr: Color) -> bool {
+    let pawns_king = state.pieces(clr, Type::Pawn) | state.pieces(clr, Type::King);
+    state.occupied(clr) & !pawns_king != 0
+}
 pub struct Searcher {
     pub nodes: u128,
+    // shared with helper threads in a multi-threaded search
+    pub table: Arc<TransTable>,
+    pub(crate) order: MoveOrder,
+    // not shared, pawn structure is cheap to recompute on a miss
+    pub(crate) pawns: PawnTable,
+    // triangular pv table, the best line found so far from each ply
+    pv_table: Vec<Vec<Move>>,
+    // each ply's move picker keeps its moves here, reused between nodes
+    picker_bufs: Vec<PickerBuf>,
+    // principal variation of the last completed iteration
+    pv: Vec<Move>,
+    // go nodes, the search stops once this many are searched
+    node_limit: u128,
+    // search threads, this one included
+    pub threads: usize,
+    // number of best lines to report
+    pub multi_pv: usize,
+    // lazy smp helpers search this much deeper than the iteration
+    pub(crate) skew: i32,
+    // root moves skipped while looking for the next multipv line
+    excluded: Vec<Move>,
+    // each multipv line of the last completed iteration, best first
+    lines: Vec<(FoundMv, Vec<Move>)>,
+    // lazy smp helpers, kept between searches with their move ordering
+    #[cfg(all(feature = "std", target_has_atomic = "64"))]
+    pub(crate) helpers: Vec<Searcher>,
+}
+
+// what a node is expected to do, from where it sits in the tree
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Node {
+    // on the principal variation, searched with an open window
+    Pv,
+    // expected to fail high, one good enough move will do
+    Cut,
+    // expected to fail low after trying every move
+    All,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -19,6 +83,9 @@ pub struct SearchParams {
     depth: i32,
     alpha: i16,
     beta: i16,
+    // distance from the root
+    ply: i32,
+    node: Node,
 }
 
 impl SearchParams {
@@ -27,13 +94,36 @@ impl SearchParams {
             depth,
             alpha: -i16::MAX,
             beta: i16::MAX,
+            ply: 0,
+            node: Node::Pv,
         }
     }
+    // the first child, searched with the same window
     fn tick(&self) -> Self {
         SearchParams {
             depth: self.depth - 1,
-            alpha: -self.beta,
-            beta: -self.alpha,
+            alpha: untick_score(self.beta),
+            beta: untick_score(self.alpha),
+            ply: self.ply + 1,
+            node: match self.node {
+                Node::Pv => Node::Pv,
+                Node::Cut => Node::All,
+                Node::All => Node::Cut,
+            },
+        }
+    }
+    // later children, with a zero window that only asks whether they beat
+    // alpha
+    fn scout(&self) -> Self {
+        let beta = untick_score(self.alpha);
+        SearchParams {
+            alpha: beta - 1,
+            beta,
+            node: match self.node {
+                Node::Pv | Node::All => Node::Cut,
+                Node::Cut => Node::All,
+            },
+            ..self.tick()
         }
     }
     fn contains(&self, score: i16) -> bool {
@@ -51,36 +141,176 @@ fn tick_score(enemy_score: i16) -> i16 {
         score
     }
 }
+// inverse of tick_score, so the window stays in the child's mate distances
+fn untick_score(score: i16) -> i16 {
+    if score.abs() >= MATE_BOUND && score.abs() <= CHECKMATE {
+        -score - if score > 0 { 1 } else { -1 }
+    } else {
+        -score
+    }
+}
 
-impl Searcher {
-    pub fn new() -> Self {
-        Searcher { nodes: 0 }
+// ply's picker buffer, made on first use
+fn picker_buf(bufs: &mut Vec<PickerBuf>, ply: i32) -> &mut PickerBuf {
+    let ply = ply as usize;
+    if bufs.len() <= ply {
+        bufs.resize_with(ply + 1, PickerBuf::default);
     }
+    &mut bufs[ply]
+}
 
+impl Searcher {
+    // table_size is the number of transposition table entries
+    pub fn new(table_size: usize) -> Self {
+        Searcher::with_table(TransTable::new(table_size))
+    }
+    // the pawn table is sized to go with table
+    pub fn with_table(table: TransTable) -> Self {
+        Searcher {
+            nodes: 0,
+            pawns: PawnTable::new(cmp::min(table.len() / 8, PAWN_TABLE_MAX)),
+            table: Arc::new(table),
+            order: MoveOrder::default(),
+            pv_table: Vec::new(),
+            picker_bufs: Vec::new(),
+            pv: Vec::new(),
+            node_limit: u128::MAX,
+            threads: 1,
+            multi_pv: 1,
+            skew: 0,
+            excluded: Vec::new(),
+            lines: Vec::new(),
+            #[cfg(all(feature = "std", target_has_atomic = "64"))]
+            helpers: Vec::new(),
+        }
+    }
+    // needed after the eval params change, the entries were scored with the old ones
+    pub fn clear_pawn_table(&mut self) {
+        self.pawns.clear();
+        #[cfg(all(feature = "std", target_has_atomic = "64"))]
+        for helper in &mut self.helpers {
+            helper.clear_pawn_table();
+        }
+    }
+    fn should_stop(&self, signal: &impl SearcherSignal) -> bool {
+        self.nodes >= self.node_limit || signal.should_stop()
+    }
+    // best line from the root, starting with the move iter_negamax returned
+    pub fn pv(&self) -> &[Move] {
+        &self.pv
+    }
+    // best multipv lines with their pvs, the first is the same as pv()
+    pub fn lines(&self) -> &[(FoundMv, Vec<Move>)] {
+        &self.lines
+    }
+    // ply's line is emptied on entering a node, so stale moves never leak
+    // into a parent's line
+    fn clear_pv(&mut self, ply: usize) {
+        if self.pv_table.len() <= ply {
+            self.pv_table.resize_with(ply + 1, Vec::new);
+        }
+        self.pv_table[ply].clear();
+    }
+    // the picker's next move at ply, in move ordering order
+    fn next_move(&mut self, picker: &mut MovePicker, state: &mut State, ply: i32) -> Option<Move> {
+        let order = &self.order;
+        let buf = picker_buf(&mut self.picker_bufs, ply);
+        picker.next(state, buf, |state, mv| order.score(state, mv))
+    }
+    // mv is the new best at ply, followed by the child's line
+    fn update_pv(&mut self, ply: usize, mv: Move) {
+        let (ours, rest) = self.pv_table.split_at_mut(ply + 1);
+        let line = &mut ours[ply];
+        line.clear();
+        line.push(mv);
+        if let Some(child) = rest.first() {
+            line.extend_from_slice(child);
+        }
+    }
     pub fn iter_negamax(
         &mut self,
         state: &mut State,
         depth: i32,
         signal: &impl SearcherSignal,
     ) -> FoundMv {
-        // use the prev_score from 2 moves ago to avoid turn instability
-        let mut _prev_score = state.fast_score();
-        let mut best_mv = (None, _prev_score);
-        for d in 1..=depth {
-            #[cfg(feature = "iterative_deepen")]
-            let found_mv = self.aspiration_negamax(state, d, _prev_score, signal);
-            #[cfg(not(feature = "iterative_deepen"))]
-            let found_mv = self.negamax(state, SearchParams::new(d), signal);
-
-            if signal.should_stop() {
+        self.search(state, SearchLimits::depth(depth), signal)
+    }
+    // runs on self.threads threads when there's more than one
+    pub fn search(
+        &mut self,
+        state: &mut State,
+        limits: SearchLimits,
+        signal: &impl SearcherSignal,
+    ) -> FoundMv {
+        self.table.new_search();
+        #[cfg(all(feature = "std", target_has_atomic = "64"))]
+        if self.threads > 1 {
+            return self.smp_search(state, limits, signal);
+        }
+        self.iterate(state, limits, signal)
+    }
+    // iterative deepening until a limit is hit, or the signal says the next
+    // iteration won't finish in time
+    pub(crate) fn iterate(
+        &mut self,
+        state: &mut State,
+        limits: SearchLimits,
+        signal: &impl SearcherSignal,
+    ) -> FoundMv {
+        self.node_limit = limits.nodes.map_or(u128::MAX, |n| self.nodes + n);
+        // use the prev_score from 2 moves ago to avoid turn instability,
+        // kept for each multipv line
+        let guess = state.eval(&mut self.pawns);
+        let mut _prev_scores = vec![guess; self.multi_pv];
+        let mut last_scores = _prev_scores.clone();
+        let mut best_mv = (None, guess);
+        self.pv.clear();
+        self.lines.clear();
+        self.order.new_search();
+        'deepen: for d in 1..=limits.depth {
+            let mut lines = Vec::with_capacity(self.multi_pv);
+            self.excluded.clear();
+            for (k, &_guess) in _prev_scores.iter().enumerate() {
+                #[cfg(feature = "iterative_deepen")]
+                let found_mv = self.aspiration_negamax(state, d + self.skew, _guess, signal);
+                #[cfg(not(feature = "iterative_deepen"))]
+                let found_mv = self.negamax(state, SearchParams::new(d + self.skew), signal);
+
+                if self.should_stop(signal) {
+                    // even a partial first iteration beats having no move
+                    if best_mv.0.is_none() && k == 0 {
+                        best_mv = found_mv;
+                        self.pv.clear();
+                        self.pv.extend(found_mv.0);
+                    }
+                    break 'deepen;
+                }
+                // fewer legal moves than lines
+                if found_mv.0.is_none() && k > 0 {
+                    break;
+                }
+                let line = (self.multi_pv > 1).then_some(k + 1);
+                signal
+                    .send_partial(self.nodes, d, line, found_mv, &self.pv_table[0])
+                    .unwrap();
+                lines.push((found_mv, self.pv_table[0].clone()));
+                self.excluded.extend(found_mv.0);
+            }
+            self.excluded.clear();
+            for (k, &(found_mv, _)) in lines.iter().enumerate() {
+                _prev_scores[k] = last_scores[k];
+                last_scores[k] = found_mv.1;
+            }
+            best_mv = lines[0].0;
+            self.pv.clear();
+            self.pv.extend_from_slice(&lines[0].1);
+            self.lines = lines;
+            if limits.found_mate(best_mv.1) || !signal.next_iteration(d, best_mv) {
                 break;
             }
-            signal.send_partial(self.nodes, d, found_mv).unwrap();
-            _prev_score = best_mv.1;
-            best_mv = found_mv;
         }
-        signal.send_best(best_mv).unwrap();
-        return best_mv;
+        signal.send_best(best_mv, &self.pv).unwrap();
+        best_mv
     }
     pub fn aspiration_negamax(
         &mut self,
@@ -91,25 +321,25 @@ impl Searcher {
     ) -> FoundMv {
         let mut spread = 30;
         let mut params = SearchParams {
-            depth,
             alpha: guess.saturating_sub(spread / 2),
             beta: guess.saturating_add(spread / 2),
+            ..SearchParams::new(depth)
         };
         loop {
             let found_mv = self.negamax(state, params, signal);
             let score = found_mv.1;
-            if params.contains(score) {
+            // a stopped search won't get further by widening the window
+            if params.contains(score) || self.should_stop(signal) {
                 return found_mv;
             }
             spread = spread.saturating_add(spread);
-            // search is stable, so we can use 1 here
-            let (sub, add) = if score <= params.alpha {
-                (spread, 1)
+            // only the side that failed moves. pruning makes the search
+            // unstable, and a window that never shrinks can't flip-flop
+            if score <= params.alpha {
+                params.alpha = cmp::max(score.saturating_sub(spread), -i16::MAX);
             } else {
-                (1, spread)
-            };
-            params.alpha = score.saturating_sub(sub);
-            params.beta = score.saturating_add(add);
+                params.beta = score.saturating_add(spread);
+            }
         }
     }
     pub fn negamax(
@@ -118,58 +348,246 @@ impl Searcher {
         mut params: SearchParams,
         signal: &impl SearcherSignal,
     ) -> FoundMv {
+        self.clear_pv(params.ply as usize);
+        // nothing scores better than mating next move or worse than being
+        // mated, so a window past either is settled already. unticking
+        // isn't one to one out there, these windows can even be empty
+        if params.alpha >= CHECKMATE - 1 {
+            return (None, CHECKMATE - 1);
+        }
+        if params.beta <= -CHECKMATE {
+            return (None, -CHECKMATE);
+        }
         debug_assert!(
             params.alpha < params.beta,
             "a={} >= b={}",
             params.alpha,
             params.beta
         );
+        // besides real draws, repeating a position from earlier in the search
+        // means neither side can make progress
+        if params.ply > 0 && (state.is_draw() || state.repetitions_within(params.ply as usize) > 0)
+        {
+            return (None, DRAW);
+        }
+        let in_check = state.in_check(state.turn());
+        // a forced reply shouldn't use up depth
+        if cfg!(feature = "check_extension") && in_check && params.ply < MAX_PLY as i32 {
+            params.depth += 1;
+        }
+        if params.depth <= 0 || self.should_stop(signal) {
+            return (None, self.quiesce(state, params, signal));
+        }
         self.nodes += 1;
-        if params.depth <= 0 || signal.should_stop() {
-            let abs_score = if NEGAMAX_HACK {
-                state.fast_score()
-            } else {
-                state.slow_score()
-            };
-            return (None, state.rel_neg(abs_score));
+        let key = state.key();
+        let orig_alpha = params.alpha;
+        let mut hash_mv = None;
+        if let Some(entry) = self.table.probe(key) {
+            // pv nodes are searched for their line, and the root always
+            // needs a move to play
+            if params.node != Node::Pv
+                && entry.depth >= params.depth
+                && entry.cutoff(params.alpha, params.beta)
+            {
+                return (entry.mv, entry.score);
+            }
+            hash_mv = entry.mv;
         }
-        let mut moves = Vec::with_capacity(state.move_count());
-        state.add_sudo_moves(&mut |mv| moves.push((mv, 0)));
 
-        // try best moves first by a shallow estimation
-        for (mv, our_score) in &mut moves {
-            state.make_move(*mv);
-            *our_score = state.rel_neg(state.fast_score());
-            state.unmake_move();
+        let eval = state.rel_neg(state.eval(&mut self.pawns));
+        let prunable = params.node != Node::Pv && !in_check && params.beta.abs() < MATE_BOUND;
+        // so far ahead that nothing in the last few plies will matter. only
+        // the margin's worth is promised, the eval can swing past it with
+        // the endgame tables
+        let rfp_score = eval - RFP_MARGIN * params.depth as i16;
+        if cfg!(feature = "reverse_futility")
+            && prunable
+            && params.depth <= RFP_DEPTH
+            && rfp_score >= params.beta
+        {
+            return (None, rfp_score);
+        }
+        // if passing still fails high, a real move will too
+        if cfg!(feature = "null_move")
+            && prunable
+            && params.depth >= NULL_MOVE_DEPTH
+            && eval >= params.beta
+            && !state.moves().last().is_some_and(|&mv| is_null(mv))
+            && has_pieces(state, state.turn())
+        {
+            let window = SearchParams {
+                alpha: params.beta - 1,
+                ..params
+            };
+            let null = SearchParams {
+                depth: params.depth - 1 - NULL_MOVE_R,
+                ..window.scout()
+            };
+            state.make_null_move();
+            let score = tick_score(self.negamax(state, null, signal).1);
+            state.unmake_null_move();
+            // mates found after passing aren't real
+            if score >= params.beta && !self.should_stop(signal) {
+                return (None, cmp::min(score, MATE_BOUND - 1));
+            }
         }
-        moves.sort_by_key(|&(_, sc)| sc);
+
+        // hash move, captures, killers, then quiets, generated as needed
+        let mut picker = self.order.picker(state, hash_mv, params.ply);
 
         let mut best_move = None;
         let mut best_score = None;
-        for (mv, _) in moves {
+        // quiets that failed to cut off, they lose history if another does
+        let mut tried = [None; TRIED_QUIETS];
+        let mut tried_len = 0;
+        let mut searched = 0;
+        while let Some(mv) = self.next_move(&mut picker, state, params.ply) {
+            if params.ply == 0 && self.excluded.contains(&mv) {
+                continue;
+            }
             state.make_move(mv);
-            if state.is_legal() {
-                // if the move is legal, check if we can raise alpha
-                let enemy_score = self.negamax(state, params.tick(), signal).1;
-                let our_score = Some(tick_score(enemy_score));
-                params.alpha = cmp::max(params.alpha, our_score.unwrap());
-                if our_score > best_score {
-                    best_score = our_score;
-                    best_move = Some(mv);
+            if !state.is_legal() {
+                state.unmake_move();
+                continue;
+            }
+            // quiet moves that don't check are the ones worth skimping on
+            let quiet =
+                params.ply > 0 && !in_check && is_quiet(mv) && !state.in_check(state.turn());
+            let futility = eval.saturating_add(FUTILITY_MARGIN * params.depth as i16);
+            if cfg!(feature = "futility")
+                && quiet
+                && params.node != Node::Pv
+                && params.depth <= FUTILITY_DEPTH
+                && futility <= params.alpha
+                && best_score.is_some()
+            {
+                // fail soft, the move might have scored as much as that
+                best_score = cmp::max(best_score, Some(futility));
+                state.unmake_move();
+                continue;
+            }
+            // cut nodes only need one move to work, the rest are long shots
+            let reduction = if cfg!(feature = "lmr")
+                && quiet
+                && params.node != Node::Pv
+                && params.depth >= LMR_DEPTH
+                && searched >= LMR_MOVES
+                && picker.stage() == Stage::Quiets
+            {
+                1 + (params.node == Node::Cut) as i32
+            } else {
+                0
+            };
+            searched += 1;
+
+            let our_score = if searched == 1 {
+                tick_score(self.negamax(state, params.tick(), signal).1)
+            } else {
+                // prove the move can't beat alpha, shallower if it's late
+                let scout = params.scout();
+                let reduced = SearchParams {
+                    depth: scout.depth - reduction,
+                    ..scout
+                };
+                let mut score = tick_score(self.negamax(state, reduced, signal).1);
+                if reduction > 0 && score > params.alpha {
+                    score = tick_score(self.negamax(state, scout, signal).1);
                 }
+                // it might, so find out by how much
+                if params.node == Node::Pv && params.contains(score) {
+                    score = tick_score(self.negamax(state, params.tick(), signal).1);
+                }
+                score
+            };
+            params.alpha = cmp::max(params.alpha, our_score);
+            if Some(our_score) > best_score {
+                best_score = Some(our_score);
+                best_move = Some(mv);
+                self.update_pv(params.ply as usize, mv);
             }
             state.unmake_move();
             // if the window closed, stop searching - this never triggers
             // calc_mate because we assume beta > alpha initially
             if params.beta <= params.alpha {
+                let tried = tried[..tried_len].iter().flatten();
+                self.order
+                    .cutoff(state, params.ply, params.depth, mv, tried);
                 break;
             }
+            if is_quiet(mv) && tried_len < TRIED_QUIETS {
+                tried[tried_len] = Some(mv);
+                tried_len += 1;
+            }
         }
         let calc_mate = || {
             let abs_score = state.end_score();
             state.rel_neg(abs_score)
         };
-        (best_move, best_score.unwrap_or_else(calc_mate))
+        let best_score = best_score.unwrap_or_else(calc_mate);
+
+        // a stopped search is only partially explored, don't trust it later.
+        // neither is a root missing the moves multipv already found
+        let excluded = params.ply == 0 && !self.excluded.is_empty();
+        if !self.should_stop(signal) && !excluded {
+            let bound = if best_score <= orig_alpha {
+                Bound::Upper
+            } else if best_score >= params.beta {
+                Bound::Lower
+            } else {
+                Bound::Exact
+            };
+            self.table
+                .store(key, best_move, params.depth, bound, best_score);
+        }
+        (best_move, best_score)
+    }
+    // captures only search past the horizon. the side to move can always
+    // "stand pat" instead of making a bad capture
+    pub fn quiesce(
+        &mut self,
+        state: &mut State,
+        mut params: SearchParams,
+        signal: &impl SearcherSignal,
+    ) -> i16 {
+        // checked before counting, so a node limit is never overshot
+        let stop = self.should_stop(signal);
+        if !stop {
+            self.nodes += 1;
+        }
+        let abs_score = if NEGAMAX_HACK {
+            state.eval(&mut self.pawns)
+        } else {
+            state.slow_score()
+        };
+        let stand_pat = state.rel_neg(abs_score);
+        if stand_pat >= params.beta || stop {
+            return stand_pat;
+        }
+        params.alpha = cmp::max(params.alpha, stand_pat);
+
+        let mut picker = MovePicker::captures();
+        let mut best_score = stand_pat;
+        while let Some(mv) = picker.next(
+            state,
+            picker_buf(&mut self.picker_bufs, params.ply),
+            |state, mv| mvv_lva(state, mv),
+        ) {
+            // losing captures can't beat standing pat
+            if state.see(mv) < 0 {
+                continue;
+            }
+            state.make_move(mv);
+            if state.is_legal() {
+                let our_score = tick_score(self.quiesce(state, params.tick(), signal));
+                params.alpha = cmp::max(params.alpha, our_score);
+                best_score = cmp::max(best_score, our_score);
+            }
+            state.unmake_move();
+            if params.beta <= params.alpha {
+                break;
+            }
+        }
+        best_score
     }
 }
 
@@ -179,36 +597,34 @@ mod test {
     use chess::testpos::*;
     use std::*;
 
+    const TEST_TABLE: usize = 1 << 16;
+
     fn get_pv(fen: &str, depth: i32) -> String {
-        let pos: State = str::parse(fen).unwrap();
-        let mut pv = Vec::new();
-        for i in 0..depth {
-            let mut state = pos.clone();
-            for &mv in &pv {
-                state.make_move(mv);
-            }
-            let (best_mv, _sc) =
-                Searcher::new().negamax(&mut state, SearchParams::new(depth - i), &BlockSignal {});
-            if let Some(mv) = best_mv {
-                pv.push(mv);
-            }
-        }
-        format!("{}", chess::show_iter(|mv| mv.to_string(), " ", pv))
-    }
-    fn do_search(fen: &str, depth: i32) -> FoundMv {
         let mut pos: State = str::parse(fen).unwrap();
-        Searcher::new().negamax(&mut pos, SearchParams::new(depth), &BlockSignal {})
+        let mut searcher = Searcher::new(TEST_TABLE);
+        searcher.negamax(&mut pos, SearchParams::new(depth), &BlockSignal {});
+        chess::show_iter(|mv| mv.to_string(), " ", &searcher.pv_table[0])
     }
-    fn do_asp_search(fen: &str, depth: i32) -> FoundMv {
+    fn do_search(fen: &str, depth: i32) -> FoundMv {
         let mut pos: State = str::parse(fen).unwrap();
-        Searcher::new().iter_negamax(&mut pos, depth, &BlockSignal {})
+        Searcher::new(TEST_TABLE).negamax(&mut pos, SearchParams::new(depth), &BlockSignal {})
     }
 
     #[test]
-    #[ignore]
-    // at a depth of 5, this can force a queen capture right at the horizon
+    fn pawn_table_size() {
+        // the mcu's table, no floor that would blow its heap
+        assert_eq!(Searcher::new(1 << 7).pawns.len(), 16);
+        assert_eq!(Searcher::new(TEST_TABLE).pawns.len(), PAWN_TABLE_MAX);
+        assert_eq!(Searcher::new(0).pawns.len(), 1);
+    }
+    #[test]
+    // at a depth of 5, c3d5 f6d5 e5c7 d8c7 e2e7 looks like it wins the queen,
+    // but f8e7 takes back right past the horizon
     fn horizon_queen() {
-        assert_eq!(get_pv(HORIZON_QUEEN, 5), "c3d5 f6d5 e5c7 d8c7 e2e7");
+        assert!(!get_pv(HORIZON_QUEEN, 5).starts_with("c3d5"));
+        // white is up a knight for a pawn, not a queen
+        let (_, sc) = do_search(HORIZON_QUEEN, 5);
+        assert!(sc < 500);
     }
     #[test]
     fn mate_in_2() {
@@ -230,23 +646,107 @@ mod test {
         assert_eq!(mv.unwrap().to_string(), "h3h1");
     }
     #[test]
-    #[ignore]
+    fn fifty_move_draw() {
+        // black is up a queen, but a2 is forced and resets nothing
+        let (mv, sc) = do_search("8/8/8/8/8/2k5/8/K6q w - - 99 80", 3);
+        assert_eq!(mv.unwrap().to_string(), "a1a2");
+        assert_eq!(sc, DRAW);
+    }
+    #[test]
+    fn pv_is_legal() {
+        for fen in &[KIWIPETE, POS_3, POS_4, POS_5, MATE_2_B] {
+            let mut state: State = str::parse(fen).unwrap();
+            let mut searcher = Searcher::new(TEST_TABLE);
+            let (mv, _) = searcher.iter_negamax(&mut state.clone(), 5, &BlockSignal {});
+            let pv = searcher.pv();
+            assert_eq!(pv.first().copied(), mv);
+            assert!(pv.len() > 1);
+            for &mv in pv {
+                assert!(state.gen_moves().contains(&mv));
+                state.make_move(mv);
+            }
+        }
+    }
+    #[test]
+    fn search_limits() {
+        let mut state: State = str::parse(KIWIPETE).unwrap();
+        let mut searcher = Searcher::new(TEST_TABLE);
+        let limits = SearchLimits {
+            nodes: Some(2000),
+            ..SearchLimits::depth(100)
+        };
+        let (mv, _) = searcher.search(&mut state, limits, &BlockSignal {});
+        assert!(mv.is_some());
+        assert!(searcher.nodes <= 2000);
+
+        let mut state: State = str::parse(MATE_2_B).unwrap();
+        let limits = SearchLimits {
+            mate: Some(2),
+            ..SearchLimits::depth(100)
+        };
+        let (mv, sc) = searcher.search(&mut state, limits, &BlockSignal {});
+        assert_eq!(mv.unwrap().to_string(), "a4c6");
+        assert_eq!(sc, chess::mate_ply(3));
+    }
+    #[test]
+    fn multi_pv() {
+        for fen in &[KIWIPETE, POS_3, MATE_2_B] {
+            let mut searcher = Searcher::new(TEST_TABLE);
+            let single = searcher.iter_negamax(&mut str::parse(fen).unwrap(), 4, &BlockSignal {});
+            searcher.multi_pv = 3;
+            searcher.table.clear();
+            let best = searcher.iter_negamax(&mut str::parse(fen).unwrap(), 4, &BlockSignal {});
+            let lines = searcher.lines();
+            assert_eq!(lines.len(), 3);
+            assert_eq!(best, lines[0].0);
+            assert_eq!(best.1, single.1);
+            for (i, ((mv, sc), pv)) in lines.iter().enumerate() {
+                assert_eq!(pv.first(), mv.as_ref());
+                assert!(lines[..i].iter().all(|((other, _), _)| other != mv));
+                if i > 0 {
+                    assert!(*sc <= lines[i - 1].0 .1);
+                }
+            }
+        }
+        // the king only has three moves
+        let mut searcher = Searcher::new(TEST_TABLE);
+        searcher.multi_pv = 5;
+        let mut state = str::parse("7k/8/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        searcher.iter_negamax(&mut state, 3, &BlockSignal {});
+        assert_eq!(searcher.lines().len(), 3);
+    }
+    // pruning depends on the window, so only a full width search has to
+    // find the same score through aspiration windows
+    #[cfg(not(any(
+        feature = "null_move",
+        feature = "lmr",
+        feature = "reverse_futility",
+        feature = "futility",
+        feature = "check_extension"
+    )))]
+    #[test]
     fn aspiration_bf() {
+        let do_asp_search = |fen: &str, depth| {
+            let mut pos: State = str::parse(fen).unwrap();
+            Searcher::new(TEST_TABLE).iter_negamax(&mut pos, depth, &BlockSignal {})
+        };
         for d in 1..=6 {
-            println!("depth {}", d);
-            for pos in &[YOUR_MOVE, KIWIPETE, POS_3, POS_4, POS_5, POS_6, DUB_M8] {
+            for pos in &[
+                YOUR_MOVE,
+                KIWIPETE,
+                POS_3,
+                POS_4,
+                POS_5,
+                POS_6,
+                DUB_M8,
+                HORIZON_QUEEN,
+                MATE_2_B,
+                ROOK_MATE_W,
+                ROOK_MATE_B,
+            ] {
                 let negamax_mv = do_search(pos, d);
                 let asp_mv = do_asp_search(pos, d);
-                if negamax_mv != asp_mv {
-                    println!("fen {}", pos);
-                    println!(
-                        "depth {}: {} vs {}",
-                        d,
-                        asp_mv.0.unwrap(),
-                        negamax_mv.0.unwrap()
-                    );
-                }
-                assert_eq!(negamax_mv.1, asp_mv.1);
+                assert_eq!(negamax_mv.1, asp_mv.1, "{} at depth {}", pos, d);
             }
         }
     }
@@ -1,23 +1,37 @@
 use super::*;
 
+use chess::Move;
+
 #[cfg(feature = "std")]
 use {
     std::sync::atomic::{AtomicBool, Ordering},
     std::sync::mpsc,
-    std::sync::Arc,
+    std::sync::{Arc, Mutex},
+    std::thread,
     std::time::{Duration, Instant},
 };
 
 #[cfg(not(feature = "std"))]
-type MsgError = ();
+pub type MsgError = ();
 #[cfg(feature = "std")]
-type MsgError = mpsc::SendError<EngineMsg>;
+pub type MsgError = mpsc::SendError<EngineMsg>;
 
-type MsgSend = Result<(), MsgError>;
+pub type MsgSend = Result<(), MsgError>;
 pub trait SearcherSignal {
     fn should_stop(&self) -> bool;
-    fn send_partial(&self, nodes: u128, depth: i32, mv: FoundMv) -> MsgSend;
-    fn send_best(&self, best: FoundMv) -> MsgSend;
+    // line is the multipv number, None when only one line is searched
+    fn send_partial(
+        &self,
+        nodes: u128,
+        depth: i32,
+        line: Option<usize>,
+        mv: FoundMv,
+        pv: &[Move],
+    ) -> MsgSend;
+    // pv is the line behind best, its second move is the one to ponder on
+    fn send_best(&self, best: FoundMv, pv: &[Move]) -> MsgSend;
+    // after each finished iteration, false ends the search there
+    fn next_iteration(&self, depth: i32, found: FoundMv) -> bool;
 }
 #[derive(Default)]
 pub struct BlockSignal {}
@@ -26,12 +40,49 @@ impl SearcherSignal for BlockSignal {
     fn should_stop(&self) -> bool {
         false
     }
-    fn send_partial(&self, _nodes: u128, _depth: i32, _mv: FoundMv) -> MsgSend {
+    fn send_partial(
+        &self,
+        _nodes: u128,
+        _depth: i32,
+        _line: Option<usize>,
+        _mv: FoundMv,
+        _pv: &[Move],
+    ) -> MsgSend {
         Result::Ok(())
     }
-    fn send_best(&self, _best: FoundMv) -> MsgSend {
+    fn send_best(&self, _best: FoundMv, _pv: &[Move]) -> MsgSend {
         Result::Ok(())
     }
+    fn next_iteration(&self, _depth: i32, _found: FoundMv) -> bool {
+        true
+    }
+}
+
+// a search started with go ponder runs on the opponent's time, untimed,
+// until ponderhit turns it into a normal search
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+pub struct Ponder {
+    pondering: AtomicBool,
+    // when the clock started, None until ponderhit
+    hit_at: Mutex<Option<Instant>>,
+}
+
+#[cfg(feature = "std")]
+impl Ponder {
+    pub fn new(pondering: bool) -> Self {
+        Ponder {
+            pondering: AtomicBool::new(pondering),
+            hit_at: Mutex::new(None),
+        }
+    }
+    pub fn pondering(&self) -> bool {
+        self.pondering.load(Ordering::Relaxed)
+    }
+    pub fn hit(&self) {
+        *self.hit_at.lock().unwrap() = Some(Instant::now());
+        self.pondering.store(false, Ordering::Relaxed);
+    }
 }
 
 #[cfg(feature = "std")]
@@ -39,17 +90,31 @@ pub struct StdSignal {
     pub stop: Arc<AtomicBool>,
     pub tx: mpsc::Sender<EngineMsg>,
     start: Instant,
+    time: TimeManager,
+    ponder: Arc<Ponder>,
 }
 
 #[cfg(feature = "std")]
 impl StdSignal {
-    pub fn new(stop: Arc<AtomicBool>, tx: mpsc::Sender<EngineMsg>) -> StdSignal {
+    pub fn new(
+        stop: Arc<AtomicBool>,
+        tx: mpsc::Sender<EngineMsg>,
+        time: TimeManager,
+        ponder: Arc<Ponder>,
+    ) -> StdSignal {
         StdSignal {
             stop,
             tx,
             start: Instant::now(),
+            time,
+            ponder,
         }
     }
+    // time on our own clock
+    fn elapsed(&self) -> Duration {
+        let hit_at = *self.ponder.hit_at.lock().unwrap();
+        hit_at.unwrap_or(self.start).elapsed()
+    }
 }
 #[cfg(feature = "std")]
 impl SearcherSignal for StdSignal {
@@ -57,20 +122,39 @@ impl SearcherSignal for StdSignal {
     fn should_stop(&self) -> bool {
         self.stop.load(Ordering::Relaxed)
     }
-    fn send_partial(&self, nodes: u128, depth: i32, mv: FoundMv) -> MsgSend {
-        let (best, score) = mv;
+    fn send_partial(
+        &self,
+        nodes: u128,
+        depth: i32,
+        line: Option<usize>,
+        mv: FoundMv,
+        pv: &[Move],
+    ) -> MsgSend {
+        let (_, score) = mv;
         let el = self.start.elapsed();
         let nps = Duration::from_secs(1).as_micros() * nodes / self.start.elapsed().as_micros();
         self.tx.send(EngineMsg::Info(UciInfo {
             depth,
+            multipv: line,
             score,
             nodes,
             nps,
             time: el.as_millis(),
-            pv: vec![best.unwrap()],
+            pv: pv.to_vec(),
         }))
     }
-    fn send_best(&self, best: FoundMv) -> MsgSend {
-        self.tx.send(EngineMsg::BestMove(best.0.unwrap()))
+    fn send_best(&self, best: FoundMv, pv: &[Move]) -> MsgSend {
+        // the gui doesn't expect a move until ponderhit or stop
+        while self.ponder.pondering() && !self.should_stop() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let ponder = pv.get(1).copied().filter(|_| pv.first() == best.0.as_ref());
+        self.tx.send(EngineMsg::BestMove(best.0.unwrap(), ponder))
+    }
+    fn next_iteration(&self, _depth: i32, found: FoundMv) -> bool {
+        let (mv, score) = found;
+        // still tracks stability while pondering, but never runs out of time
+        let in_time = self.time.next_iteration(self.elapsed(), mv, score);
+        in_time || self.ponder.pondering()
     }
 }
@@ -0,0 +1,162 @@
+use super::*;
+
+use chess::{Move, PawnTable, State};
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+
+use core::mem;
+
+use alloc::vec::Vec;
+
+// lazy smp helpers search the same position to fill the shared table,
+// and only report their node counts
+struct HelperSignal<'a> {
+    done: &'a AtomicBool,
+    nodes: &'a AtomicU64,
+}
+impl SearcherSignal for HelperSignal<'_> {
+    #[inline]
+    fn should_stop(&self) -> bool {
+        self.done.load(Ordering::Relaxed)
+    }
+    fn send_partial(
+        &self,
+        nodes: u128,
+        _depth: i32,
+        _line: Option<usize>,
+        _mv: FoundMv,
+        _pv: &[Move],
+    ) -> MsgSend {
+        self.nodes.store(nodes as u64, Ordering::Relaxed);
+        Ok(())
+    }
+    fn send_best(&self, _best: FoundMv, _pv: &[Move]) -> MsgSend {
+        Ok(())
+    }
+    fn next_iteration(&self, _depth: i32, _found: FoundMv) -> bool {
+        true
+    }
+}
+
+// the main thread's signal, with the helpers' nodes added to its own
+struct MainSignal<'a, S> {
+    inner: &'a S,
+    helper_nodes: &'a [AtomicU64],
+}
+impl<S: SearcherSignal> SearcherSignal for MainSignal<'_, S> {
+    #[inline]
+    fn should_stop(&self) -> bool {
+        self.inner.should_stop()
+    }
+    fn send_partial(
+        &self,
+        nodes: u128,
+        depth: i32,
+        line: Option<usize>,
+        mv: FoundMv,
+        pv: &[Move],
+    ) -> MsgSend {
+        let helper_nodes: u64 = self
+            .helper_nodes
+            .iter()
+            .map(|n| n.load(Ordering::Relaxed))
+            .sum();
+        self.inner
+            .send_partial(nodes + helper_nodes as u128, depth, line, mv, pv)
+    }
+    fn send_best(&self, best: FoundMv, pv: &[Move]) -> MsgSend {
+        self.inner.send_best(best, pv)
+    }
+    fn next_iteration(&self, depth: i32, found: FoundMv) -> bool {
+        self.inner.next_iteration(depth, found)
+    }
+}
+
+impl Searcher {
+    // this thread searches as usual while the helpers share its table. the
+    // search ends when this thread's does
+    pub(crate) fn smp_search(
+        &mut self,
+        state: &mut State,
+        limits: SearchLimits,
+        signal: &impl SearcherSignal,
+    ) -> FoundMv {
+        let done = AtomicBool::new(false);
+        let helper_nodes: Vec<_> = (1..self.threads).map(|_| AtomicU64::new(0)).collect();
+        // the helpers are only built when the thread count goes up, their
+        // history and pawn tables carry over like this thread's
+        let mut helpers = mem::take(&mut self.helpers);
+        helpers.resize_with(self.threads - 1, || {
+            let mut helper = Searcher::new(0);
+            helper.pawns = PawnTable::new(self.pawns.len());
+            helper
+        });
+        let found = thread::scope(|scope| {
+            for (i, (helper, nodes)) in helpers.iter_mut().zip(&helper_nodes).enumerate() {
+                helper.nodes = 0;
+                helper.table = self.table.clone();
+                // half the helpers stay a ply ahead, so the threads spread
+                // out over depths instead of all repeating the same work
+                helper.skew = (i % 2 == 0) as i32;
+                let mut state = state.clone();
+                let signal = HelperSignal { done: &done, nodes };
+                let limits = SearchLimits {
+                    nodes: None,
+                    ..limits
+                };
+                scope.spawn(move || helper.iterate(&mut state, limits, &signal));
+            }
+            let signal = MainSignal {
+                inner: signal,
+                helper_nodes: &helper_nodes,
+            };
+            let found = self.iterate(state, limits, &signal);
+            done.store(true, Ordering::Relaxed);
+            found
+        });
+        // the helpers have all stopped by now
+        for nodes in &helper_nodes {
+            self.nodes += nodes.load(Ordering::Relaxed) as u128;
+        }
+        self.helpers = helpers;
+        found
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chess::testpos::*;
+
+    #[test]
+    fn threads_agree() {
+        for fen in &[KIWIPETE, POS_4, MATE_2_B] {
+            let mut state: State = str::parse(fen).unwrap();
+            let mut searcher = Searcher::new(1 << 16);
+            searcher.threads = 4;
+            let (mv, sc) = searcher.iter_negamax(&mut state, 4, &BlockSignal {});
+            assert!(state.gen_moves().contains(&mv.unwrap()));
+            assert_eq!(searcher.pv().first().copied(), mv);
+            if *fen == MATE_2_B {
+                assert_eq!(sc, chess::mate_ply(3));
+            }
+        }
+    }
+    #[test]
+    fn helpers_kept() {
+        let mut state: State = str::parse(KIWIPETE).unwrap();
+        let mut searcher = Searcher::new(1 << 16);
+        searcher.threads = 4;
+        searcher.iter_negamax(&mut state, 3, &BlockSignal {});
+        assert_eq!(searcher.helpers.len(), 3);
+        assert_eq!(searcher.helpers[0].pawns.len(), searcher.pawns.len());
+        // the same helpers, not new ones
+        let first: *const Searcher = &searcher.helpers[0];
+        searcher.iter_negamax(&mut state, 3, &BlockSignal {});
+        assert!(core::ptr::eq(first, &searcher.helpers[0]));
+        searcher.threads = 2;
+        searcher.iter_negamax(&mut state, 3, &BlockSignal {});
+        assert_eq!(searcher.helpers.len(), 1);
+    }
+}
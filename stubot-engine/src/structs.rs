@@ -11,11 +11,13 @@ pub enum EngineMsg {
     Input(String),
     Output(String),
     Info(UciInfo),
-    BestMove(Move),
+    // best move, and the reply we expect
+    BestMove(Move, Option<Move>),
 }
 
 pub struct UciInfo {
     pub depth: i32,
+    pub multipv: Option<usize>,
     pub score: i16,
     pub nodes: u128,
     pub nps: u128,
@@ -27,6 +29,7 @@ impl fmt::Display for UciInfo {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let UciInfo {
             depth,
+            multipv,
             score,
             nodes,
             nps,
@@ -40,10 +43,13 @@ impl fmt::Display for UciInfo {
         } else {
             format!("cp {}", score)
         };
+        write!(f, "depth {} ", depth)?;
+        if let Some(k) = multipv {
+            write!(f, "multipv {} ", k)?;
+        }
         write!(
             f,
-            "depth {} score {} nodes {} nps {} time {} pv {}",
-            depth,
+            "score {} nodes {} nps {} time {} pv {}",
             score_str,
             nodes,
             nps,
@@ -0,0 +1,267 @@
+use chess::{CastleSide, Move, MvExtra, Pos, Type};
+
+#[cfg(not(target_has_atomic = "64"))]
+use core::cell::Cell;
+use core::mem;
+#[cfg(target_has_atomic = "64")]
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use alloc::vec::Vec;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bound {
+    // score is the real value
+    Exact,
+    // failed high, score is a lower bound
+    Lower,
+    // failed low, score is an upper bound
+    Upper,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Entry {
+    pub key: u64,
+    pub mv: Option<Move>,
+    pub depth: i32,
+    pub bound: Bound,
+    // scores are relative to the node they were found at. mate scores count
+    // ply up from the mated leaf (see tick_score), so they don't depend on
+    // the path used to reach the position and can be stored as-is
+    pub score: i16,
+    generation: u8,
+}
+
+impl Entry {
+    // whether the stored score settles a search of this window
+    pub fn cutoff(&self, alpha: i16, beta: i16) -> bool {
+        match self.bound {
+            Bound::Exact => true,
+            Bound::Lower => self.score >= beta,
+            Bound::Upper => self.score <= alpha,
+        }
+    }
+}
+
+// a 64 bit cell that can be shared between search threads where the
+// target allows it. small targets without 64 bit atomics are single threaded
+#[cfg(target_has_atomic = "64")]
+#[derive(Default)]
+struct Word(AtomicU64);
+#[cfg(target_has_atomic = "64")]
+impl Word {
+    fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+    fn set(&self, val: u64) {
+        self.0.store(val, Ordering::Relaxed)
+    }
+}
+#[cfg(not(target_has_atomic = "64"))]
+#[derive(Default)]
+struct Word(Cell<u64>);
+#[cfg(not(target_has_atomic = "64"))]
+impl Word {
+    fn get(&self) -> u64 {
+        self.0.get()
+    }
+    fn set(&self, val: u64) {
+        self.0.set(val)
+    }
+}
+
+const TYPES: [Type; 6] = [
+    Type::Pawn,
+    Type::Knight,
+    Type::Bishop,
+    Type::Rook,
+    Type::Queen,
+    Type::King,
+];
+
+// 6 bits per square, 3 for the capture and 4 for the extra, 0 meaning none
+fn pack_mv(mv: Move) -> u64 {
+    let sq = |pos: Pos| (pos.y * 8 + pos.x) as u64;
+    let capture = mv.capture.map_or(0, |typ| typ as u64 + 1);
+    let extra = match mv.extra {
+        None => 0,
+        Some(MvExtra::EnPassant) => 1,
+        Some(MvExtra::Castle(side)) => 2 + side as u64,
+        Some(MvExtra::Promote(typ)) => 4 + typ as u64,
+    };
+    sq(mv.a) | sq(mv.b) << 6 | capture << 12 | extra << 15
+}
+fn unpack_mv(bits: u64) -> Move {
+    let pos = |sq: u64| Pos {
+        y: (sq >> 3 & 7) as i8,
+        x: (sq & 7) as i8,
+    };
+    let capture = (bits >> 12 & 7) as usize;
+    let extra = match bits >> 15 & 15 {
+        0 => None,
+        1 => Some(MvExtra::EnPassant),
+        2 => Some(MvExtra::Castle(CastleSide::Long)),
+        3 => Some(MvExtra::Castle(CastleSide::Short)),
+        n => Some(MvExtra::Promote(TYPES[n as usize - 4])),
+    };
+    Move {
+        a: pos(bits),
+        b: pos(bits >> 6),
+        capture: capture.checked_sub(1).map(|i| TYPES[i]),
+        extra,
+    }
+}
+
+// everything but the key in one word. the low 20 bits are the move with a
+// presence bit, then depth, bound, score and generation
+fn pack(entry: &Entry) -> u64 {
+    let mv = entry.mv.map_or(0, |mv| pack_mv(mv) | 1 << 19);
+    let depth = entry.depth.clamp(0, u8::MAX as i32) as u64;
+    let bound = entry.bound as u64;
+    let score = entry.score as u16 as u64;
+    mv | depth << 20 | bound << 28 | score << 30 | (entry.generation as u64) << 46
+}
+fn unpack(key: u64, data: u64) -> Entry {
+    let bound = match data >> 28 & 3 {
+        0 => Bound::Exact,
+        1 => Bound::Lower,
+        _ => Bound::Upper,
+    };
+    Entry {
+        key,
+        mv: Some(unpack_mv(data)).filter(|_| data & 1 << 19 != 0),
+        depth: (data >> 20 & 0xff) as i32,
+        bound,
+        score: (data >> 30) as u16 as i16,
+        generation: (data >> 46) as u8,
+    }
+}
+
+// fixed-size hash table indexed by zobrist key. the size is picked by the
+// caller so small targets (mcu, wasm) can use a tiny table.
+// entries are two words, the key is stored xored with the data so a torn
+// write from another thread just looks like a different position
+pub struct TransTable {
+    keys: Vec<Word>,
+    data: Vec<Word>,
+    generation: Word,
+}
+
+impl TransTable {
+    // size is in entries, rounded down to a power of 2
+    pub fn new(size: usize) -> Self {
+        let size = match size {
+            0 => 1,
+            n => 1 << (usize::BITS - 1 - n.leading_zeros()),
+        };
+        let words = || (0..size).map(|_| Word::default()).collect();
+        TransTable {
+            keys: words(),
+            data: words(),
+            generation: Word::default(),
+        }
+    }
+    // as many entries as fit in mb megabytes, for the uci Hash option
+    pub fn with_mb(mb: usize) -> Self {
+        TransTable::new((mb << 20) / (2 * mem::size_of::<Word>()))
+    }
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+    fn idx(&self, key: u64) -> usize {
+        key as usize & (self.keys.len() - 1)
+    }
+    pub fn clear(&self) {
+        for (k, d) in self.keys.iter().zip(&self.data) {
+            k.set(0);
+            d.set(0);
+        }
+        self.generation.set(0);
+    }
+    // call once per search so old entries get replaced first
+    pub fn new_search(&self) {
+        self.generation
+            .set(self.generation.get().wrapping_add(1) & 0xff);
+    }
+    pub fn probe(&self, key: u64) -> Option<Entry> {
+        let idx = self.idx(key);
+        let data = self.data[idx].get();
+        // an empty slot is all zeros, and no position has a zero key
+        if data == 0 || self.keys[idx].get() ^ data != key {
+            return None;
+        }
+        Some(unpack(key, data))
+    }
+    // replace-by-depth, except entries left over from older searches
+    pub fn store(&self, key: u64, mv: Option<Move>, depth: i32, bound: Bound, score: i16) {
+        let generation = self.generation.get() as u8;
+        let idx = self.idx(key);
+        let old = self.data[idx].get();
+        if old != 0 {
+            let old = unpack(0, old);
+            if old.generation == generation && old.depth > depth {
+                return;
+            }
+        }
+        let data = pack(&Entry {
+            key,
+            mv,
+            depth,
+            bound,
+            score,
+            generation,
+        });
+        self.keys[idx].set(key ^ data);
+        self.data[idx].set(data);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chess::{testpos::*, State, CHECKMATE};
+
+    const ENP: &str = "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3";
+
+    #[test]
+    fn replace_by_depth() {
+        let tt = TransTable::new(100);
+        assert_eq!(tt.len(), 64);
+        tt.store(3, None, 5, Bound::Exact, 10);
+        // collides with 3
+        tt.store(67, None, 2, Bound::Lower, 20);
+        assert_eq!(tt.probe(3).unwrap().score, 10);
+        assert!(tt.probe(67).is_none());
+
+        tt.new_search();
+        tt.store(67, None, 2, Bound::Lower, 20);
+        assert!(tt.probe(3).is_none());
+        assert_eq!(tt.probe(67).unwrap().bound, Bound::Lower);
+    }
+    #[test]
+    fn table_mb() {
+        let bytes = TransTable::with_mb(32).len() * 2 * mem::size_of::<Word>();
+        assert!(bytes <= 32 << 20 && bytes > 16 << 20);
+        assert_eq!(TransTable::with_mb(0).len(), 1);
+    }
+    #[test]
+    fn pack_entries() {
+        let tt = TransTable::new(1 << 10);
+        for fen in [KIWIPETE, POS_3, POS_4, POS_5, ENP] {
+            let mut state: State = str::parse(fen).unwrap();
+            for (i, mv) in state.gen_moves().into_iter().enumerate() {
+                let key = state.key() ^ i as u64;
+                let score = if i % 2 == 0 { -(i as i16) } else { CHECKMATE };
+                tt.store(key, Some(mv), 5, Bound::Upper, score);
+                let entry = tt.probe(key).unwrap();
+                assert_eq!(entry.mv, Some(mv));
+                assert_eq!(entry.score, score);
+                assert_eq!((entry.depth, entry.bound), (5, Bound::Upper));
+            }
+        }
+        tt.clear();
+        assert!(tt.probe(State::default().key()).is_none());
+    }
+}
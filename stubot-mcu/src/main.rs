@@ -44,7 +44,9 @@ fn main() -> ! {
         .unwrap();
 
     let mut state = State::default();
-    let mut searcher = Searcher::new();
+    // entries are 16 bytes, keep the table well inside the heap. the pawn
+    // table gets an eighth as many
+    let mut searcher = Searcher::new(1 << 7);
 
     loop {
         writeln!(usart, "{}", state.board_string()).unwrap();
@@ -0,0 +1,202 @@
+mod parse;
+mod write;
+
+pub use parse::parse_games;
+
+use chess::{ChessParseError, Color, Move, State};
+
+use std::fmt;
+
+// a move plus everything attached to it in the movetext
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub mv: Move,
+    // numeric annotation glyphs, $1 is !, $2 is ? and so on
+    pub nags: Vec<u8>,
+    pub comment: Option<String>,
+    // alternatives to this move, played from the position before it
+    pub variations: Vec<Line>,
+}
+
+impl Node {
+    pub fn new(mv: Move) -> Self {
+        Node {
+            mv,
+            nags: vec![],
+            comment: None,
+            variations: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Line {
+    // comment before the first move
+    pub comment: Option<String>,
+    pub moves: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Game {
+    // tag pairs in the order they are written
+    pub tags: Vec<(String, String)>,
+    pub start: State,
+    pub line: Line,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PgnError {
+    Tag(String),
+    Comment,
+    Fen(ChessParseError),
+    Move(String),
+    Variation,
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PgnError::Tag(s) => write!(f, "bad tag pair: {}", s),
+            PgnError::Comment => write!(f, "unterminated comment"),
+            PgnError::Fen(e) => write!(f, "bad FEN tag: {}", e),
+            PgnError::Move(s) => write!(f, "illegal or ambiguous move: {}", s),
+            PgnError::Variation => write!(f, "unbalanced variation"),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+// tags every pgn export should have, in order
+const SEVEN_TAGS: [(&str, &str); 7] = [
+    ("Event", "?"),
+    ("Site", "?"),
+    ("Date", "????.??.??"),
+    ("Round", "?"),
+    ("White", "?"),
+    ("Black", "?"),
+    ("Result", "*"),
+];
+
+impl Game {
+    // empty game, with SetUp/FEN tags if start isn't the initial position
+    pub fn new(start: State) -> Self {
+        let mut game = Game {
+            tags: SEVEN_TAGS
+                .iter()
+                .map(|&(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            start,
+            line: Default::default(),
+        };
+        if game.start.chess960() {
+            game.set_tag("Variant", "Chess960");
+        }
+        let fen = game.start.to_string();
+        if fen != State::default().to_string() {
+            game.set_tag("SetUp", "1");
+            game.set_tag("FEN", &fen);
+        }
+        game
+    }
+    // the moves played so far in state as a game, result included
+    pub fn from_state(state: &State) -> Self {
+        let mut start = state.clone();
+        for _ in 0..start.move_len() {
+            start.unmake_move();
+        }
+        let mut game = Game::new(start);
+        game.line.moves = state.moves().iter().map(|&mv| Node::new(mv)).collect();
+        game.set_tag("Result", &result(&mut state.clone()));
+        game
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(k, _)| k == name) {
+            Some(tag) => tag.1 = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+    pub fn result(&self) -> &str {
+        self.tag("Result").unwrap_or("*")
+    }
+
+    // position after the main line
+    pub fn end_state(&self) -> State {
+        let mut state = self.start.clone();
+        for node in &self.line.moves {
+            state.make_move(node.mv);
+        }
+        state
+    }
+}
+
+// result token for a finished game, * if it's still going
+fn result(state: &mut State) -> String {
+    let over = state.gen_moves().is_empty();
+    if over && state.in_check(state.turn()) {
+        match state.turn() {
+            Color::White => "0-1",
+            Color::Black => "1-0",
+        }
+    } else if over || state.is_draw() {
+        "1/2-1/2"
+    } else {
+        "*"
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chess::testpos::*;
+    use engine::{BlockSignal, Searcher};
+
+    // the engine plays itself for a few moves
+    fn self_play(fen: &str, plies: usize) -> State {
+        let mut state: State = str::parse(fen).unwrap();
+        let mut searcher = Searcher::new(1 << 12);
+        for _ in 0..plies {
+            match searcher.iter_negamax(&mut state.clone(), 2, &BlockSignal {}) {
+                (Some(mv), _) => state.make_move(mv),
+                (None, _) => break,
+            }
+        }
+        state
+    }
+
+    #[test]
+    fn engine_round_trip() {
+        let start = State::default().to_string();
+        for fen in [start.as_str(), KIWIPETE, POS_5, MATE_2_B] {
+            let state = self_play(fen, 40);
+            let game = Game::from_state(&state);
+            let text = game.to_string();
+            let read: Game = str::parse(&text).unwrap();
+            assert_eq!(read.tags, game.tags, "{}", text);
+            assert_eq!(read.start.to_string(), fen);
+            assert_eq!(read.end_state().moves(), state.moves());
+            assert_eq!(read.to_string(), text);
+        }
+    }
+    #[test]
+    fn setup_tags() {
+        let game = Game::new(State::default());
+        assert_eq!(game.tag("FEN"), None);
+        let game = Game::new(str::parse(KIWIPETE).unwrap());
+        assert_eq!(game.tag("SetUp"), Some("1"));
+        assert_eq!(game.tag("FEN"), Some(KIWIPETE));
+    }
+    #[test]
+    fn mate_result() {
+        let state = self_play(ROOK_MATE_W, 1);
+        assert_eq!(Game::from_state(&state).result(), "1-0");
+    }
+}
@@ -0,0 +1,347 @@
+use super::*;
+
+use std::iter::Peekable;
+use std::str::{Chars, FromStr};
+use std::vec;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    Open,
+    Close,
+    // 1-0, 0-1, 1/2-1/2 or *
+    Result(String),
+    San(String),
+}
+
+// move suffixes that are shorthand for a nag
+const SUFFIX_NAGS: [(&str, u8); 6] = [
+    ("!", 1),
+    ("?", 2),
+    ("!!", 3),
+    ("??", 4),
+    ("!?", 5),
+    ("?!", 6),
+];
+
+fn read_tag(chars: &mut Peekable<Chars>) -> Result<Token, PgnError> {
+    let mut raw = String::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut value = String::new();
+    loop {
+        let c = chars.next().ok_or_else(|| PgnError::Tag(raw.clone()))?;
+        if quoted {
+            match c {
+                '\\' if !escaped => escaped = true,
+                '"' if !escaped => quoted = false,
+                _ => {
+                    value.push(c);
+                    escaped = false;
+                }
+            }
+        } else if c == '"' {
+            quoted = true;
+        } else if c == ']' {
+            break;
+        }
+        raw.push(c);
+    }
+    // raw is everything between the brackets, only the name is unquoted
+    let name = raw.split('"').next().unwrap_or("").trim();
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(PgnError::Tag(raw));
+    }
+    Ok(Token::Tag(name.to_string(), value))
+}
+
+// a word is a move, possibly with a move number and suffix, or a result
+fn push_word(word: &str, tokens: &mut Vec<Token>) {
+    if ["1-0", "0-1", "1/2-1/2", "*"].contains(&word) {
+        return tokens.push(Token::Result(word.to_string()));
+    }
+    // 12. or 12... glued to the move or on its own
+    let mut word = word;
+    let digits = word
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(word.len());
+    if digits > 0 && word[digits..].starts_with('.') {
+        word = word[digits..].trim_start_matches('.');
+    }
+    let san = word.trim_end_matches(['!', '?']);
+    if !san.is_empty() {
+        tokens.push(Token::San(san.to_string()));
+    }
+    let suffix = &word[san.len()..];
+    if let Some(&(_, nag)) = SUFFIX_NAGS.iter().find(|(s, _)| *s == suffix) {
+        tokens.push(Token::Nag(nag));
+    }
+}
+
+fn lex(src: &str) -> Result<Vec<Token>, PgnError> {
+    let mut tokens = vec![];
+    let mut chars = src.chars().peekable();
+    let mut line_start = true;
+    while let Some(c) = chars.next() {
+        let was_line_start = line_start;
+        line_start = c == '\n';
+        match c {
+            // escape mechanism, the rest of the line is ignored
+            '%' if was_line_start => {
+                chars.by_ref().find(|&c| c == '\n');
+                line_start = true;
+            }
+            _ if c.is_whitespace() => (),
+            '[' => tokens.push(read_tag(&mut chars)?),
+            '{' => {
+                let mut comment = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => comment.push(c),
+                        None => return Err(PgnError::Comment),
+                    }
+                }
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            }
+            ';' => {
+                let comment: String = chars.by_ref().take_while(|&c| c != '\n').collect();
+                tokens.push(Token::Comment(comment.trim().to_string()));
+                line_start = true;
+            }
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '$' => {
+                let mut num = String::new();
+                while let Some(&d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                    num.push(d);
+                    chars.next();
+                }
+                let nag = str::parse(&num).map_err(|_| PgnError::Move(format!("${}", num)))?;
+                tokens.push(Token::Nag(nag));
+            }
+            _ => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "[]{}();$".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                push_word(&word, &mut tokens);
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Peekable<vec::IntoIter<Token>>,
+}
+
+impl Parser {
+    // reads moves into line until the end of the variation or game.
+    // state is left as it was
+    fn parse_line(
+        &mut self,
+        state: &mut State,
+        line: &mut Line,
+        nested: bool,
+    ) -> Result<(), PgnError> {
+        let res = self.parse_moves(state, line, nested);
+        for _ in &line.moves {
+            state.unmake_move();
+        }
+        res
+    }
+    fn parse_moves(
+        &mut self,
+        state: &mut State,
+        line: &mut Line,
+        nested: bool,
+    ) -> Result<(), PgnError> {
+        loop {
+            match self.tokens.peek() {
+                Some(Token::Close) if nested => {
+                    self.tokens.next();
+                    return Ok(());
+                }
+                Some(Token::Close) | Some(Token::Result(_)) | Some(Token::Tag(..)) | None => {
+                    return match nested {
+                        true => Err(PgnError::Variation),
+                        false => Ok(()),
+                    };
+                }
+                _ => (),
+            }
+            match (self.tokens.next().unwrap(), line.moves.last_mut()) {
+                (Token::Comment(c), None) => append_comment(&mut line.comment, c),
+                (Token::Comment(c), Some(node)) => append_comment(&mut node.comment, c),
+                (Token::Nag(nag), Some(node)) => node.nags.push(nag),
+                (Token::Nag(nag), None) => return Err(PgnError::Move(format!("${}", nag))),
+                (Token::Open, Some(node)) => {
+                    // the variation replaces the last move
+                    let mv = node.mv;
+                    state.unmake_move();
+                    let mut var = Line::default();
+                    let res = self.parse_line(state, &mut var, true);
+                    state.make_move(mv);
+                    res?;
+                    line.moves.last_mut().unwrap().variations.push(var);
+                }
+                (Token::Open, None) => return Err(PgnError::Variation),
+                (Token::San(san), _) => {
+                    let mv = state.parse_san(&san).map_err(|_| {
+                        PgnError::Move(format!("{} at move {}", san, state.full_move()))
+                    })?;
+                    state.make_move(mv);
+                    line.moves.push(Node::new(mv));
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    fn parse_game(&mut self) -> Result<Option<Game>, PgnError> {
+        let mut tags = vec![];
+        while let Some(Token::Tag(..)) = self.tokens.peek() {
+            if let Some(Token::Tag(k, v)) = self.tokens.next() {
+                tags.push((k, v));
+            }
+        }
+        if tags.is_empty() && self.tokens.peek().is_none() {
+            return Ok(None);
+        }
+        let mut start = State::default();
+        if let Some((_, fen)) = tags.iter().find(|(k, _)| k == "FEN") {
+            start = str::parse(fen).map_err(PgnError::Fen)?;
+        }
+        if let Some((_, variant)) = tags.iter().find(|(k, _)| k == "Variant") {
+            start.set_chess960(variant.to_lowercase().contains("960"));
+        }
+        let mut game = Game {
+            tags,
+            start,
+            line: Default::default(),
+        };
+        self.parse_line(&mut game.start.clone(), &mut game.line, false)?;
+        match self.tokens.peek() {
+            Some(Token::Result(res)) => {
+                if game.tag("Result").is_none() {
+                    game.set_tag("Result", &res.clone());
+                }
+                self.tokens.next();
+            }
+            Some(Token::Close) => return Err(PgnError::Variation),
+            _ => (),
+        }
+        Ok(Some(game))
+    }
+}
+
+fn append_comment(comment: &mut Option<String>, text: String) {
+    *comment = Some(match comment.take() {
+        Some(old) => old + " " + &text,
+        None => text,
+    });
+}
+
+// every game in a pgn file
+pub fn parse_games(src: &str) -> Result<Vec<Game>, PgnError> {
+    let mut parser = Parser {
+        tokens: lex(src)?.into_iter().peekable(),
+    };
+    let mut games = vec![];
+    while let Some(game) = parser.parse_game()? {
+        games.push(game);
+    }
+    Ok(games)
+}
+
+// the first game in a pgn file
+impl FromStr for Game {
+    type Err = PgnError;
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        let mut games = parse_games(src)?;
+        match games.len() {
+            0 => Ok(Game::new(State::default())),
+            _ => Ok(games.swap_remove(0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ANNOTATED: &str = r#"
+[Event "Casual \"blitz\""]
+[Site "?"]
+[Result "1-0"]
+
+{Intro} 1. e4 e5 2. Nf3 $1 {The usual} (2. f4!? exf4 (2... d5) 3. Nf3) 2... Nc6
+; rest of line comment
+3. Bb5 a6?! 4. Ba4 1-0
+
+[Event "Second"]
+[SetUp "1"]
+[FEN "5k2/8/5K1R/8/8/8/8/8 w - - 0 1"]
+
+1. Rh8# *
+"#;
+
+    #[test]
+    fn parse_annotated() {
+        let games = parse_games(ANNOTATED).unwrap();
+        assert_eq!(games.len(), 2);
+        let game = &games[0];
+        assert_eq!(game.tag("Event"), Some("Casual \"blitz\""));
+        assert_eq!(game.result(), "1-0");
+        assert_eq!(game.line.comment.as_deref(), Some("Intro"));
+        let moves = &game.line.moves;
+        assert_eq!(moves.len(), 7);
+        assert_eq!(moves[2].nags, vec![1]);
+        assert_eq!(moves[2].comment.as_deref(), Some("The usual"));
+        assert_eq!(moves[3].comment.as_deref(), Some("rest of line comment"));
+        assert_eq!(moves[5].nags, vec![6]);
+
+        // 2. f4!? exf4 (2... d5) 3. Nf3 replaces 2. Nf3
+        let var = &moves[2].variations[0];
+        assert_eq!(var.moves[0].mv.to_string(), "f2f4");
+        assert_eq!(var.moves[0].nags, vec![5]);
+        assert_eq!(var.moves[1].variations[0].moves[0].mv.to_string(), "d7d5");
+        assert_eq!(var.moves[2].mv.to_string(), "g1f3");
+
+        let game = &games[1];
+        assert_eq!(game.result(), "*");
+        assert_eq!(game.line.moves[0].mv.to_string(), "h6h8");
+    }
+    #[test]
+    fn parse_errors() {
+        let err = |src: &str| str::parse::<Game>(src).unwrap_err();
+        assert_eq!(err("1. e4 e4"), PgnError::Move("e4 at move 1".to_string()));
+        assert_eq!(err("1. e4 (1. d4"), PgnError::Variation);
+        assert_eq!(err("1. e4 e5)"), PgnError::Variation);
+        assert_eq!(err("( 1. e4 )"), PgnError::Variation);
+        assert_eq!(err("1. e4 {oops"), PgnError::Comment);
+        assert!(matches!(err("[FEN \"8/8 w - - 0 1\"]"), PgnError::Fen(_)));
+        assert!(matches!(err("[Event \"?\" 1. e4"), PgnError::Tag(_)));
+    }
+    #[test]
+    fn chess960_variant() {
+        let src = "[Variant \"Chess960\"]\n[FEN \"1r2k1r1/8/8/8/8/8/8/1R2K1R1 w GBgb - 0 1\"]\n\n1. O-O-O *";
+        let game: Game = str::parse(src).unwrap();
+        assert!(game.start.chess960());
+        assert_eq!(game.line.moves[0].mv.to_string(), "e1b1");
+    }
+    #[test]
+    fn annotated_round_trip() {
+        for game in parse_games(ANNOTATED).unwrap() {
+            let text = game.to_string();
+            assert_eq!(str::parse::<Game>(&text).unwrap(), game, "{}", text);
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use super::*;
+
+// export format keeps lines under 80 characters
+const LINE_WIDTH: usize = 79;
+
+// collects movetext words, gluing parentheses to their neighbours
+#[derive(Default)]
+struct Words {
+    words: Vec<String>,
+    open: bool,
+}
+
+impl Words {
+    fn push(&mut self, word: String) {
+        match self.words.last_mut() {
+            Some(last) if self.open => *last += &word,
+            _ => self.words.push(word),
+        }
+        self.open = false;
+    }
+    fn open(&mut self) {
+        self.words.push("(".to_string());
+        self.open = true;
+    }
+    fn close(&mut self) {
+        *self.words.last_mut().unwrap() += ")";
+    }
+
+    fn line(&mut self, state: &mut State, line: &Line) {
+        // black's move needs a number after anything that interrupts the moves
+        let mut need_num = true;
+        if let Some(comment) = &line.comment {
+            self.push(format!("{{{}}}", comment));
+        }
+        for node in &line.moves {
+            match state.turn() {
+                Color::White => self.push(format!("{}.", state.full_move())),
+                Color::Black if need_num => self.push(format!("{}...", state.full_move())),
+                Color::Black => (),
+            }
+            self.push(state.move_to_san(node.mv));
+            for nag in &node.nags {
+                self.push(format!("${}", nag));
+            }
+            need_num = false;
+            if let Some(comment) = &node.comment {
+                self.push(format!("{{{}}}", comment));
+                need_num = true;
+            }
+            for var in &node.variations {
+                self.open();
+                self.line(state, var);
+                self.close();
+                need_num = true;
+            }
+            state.make_move(node.mv);
+        }
+        for _ in &line.moves {
+            state.unmake_move();
+        }
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, value) in &self.tags {
+            writeln!(f, "[{} \"{}\"]", name, escape(value))?;
+        }
+        writeln!(f)?;
+
+        let mut words = Words::default();
+        words.line(&mut self.start.clone(), &self.line);
+        words.push(self.result().to_string());
+
+        let mut width = 0;
+        for word in words.words {
+            if width > 0 && width + 1 + word.len() > LINE_WIDTH {
+                writeln!(f)?;
+                width = 0;
+            }
+            if width > 0 {
+                write!(f, " ")?;
+                width += 1;
+            }
+            write!(f, "{}", word)?;
+            width += word.len();
+        }
+        writeln!(f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn write_movetext() {
+        let src = "1. e4 {best by test} 1... e5 (1... c5 $2 2. Nf3) 2. Nf3 *";
+        let game: Game = str::parse(src).unwrap();
+        assert_eq!(game.to_string(), format!("[Result \"*\"]\n\n{}\n", src));
+    }
+    #[test]
+    fn wrap_lines() {
+        let mut state = State::default();
+        for _ in 0..30 {
+            state.run_moves("g1f3 g8f6 f3g1 f6g8".split(' '));
+        }
+        let text = Game::from_state(&state).to_string();
+        assert!(text.lines().all(|l| l.len() <= LINE_WIDTH));
+        assert!(text.lines().count() > 10);
+    }
+}
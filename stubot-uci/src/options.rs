@@ -0,0 +1,231 @@
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OptKind {
+    Check(bool),
+    Spin { default: i64, min: i64, max: i64 },
+    Button,
+    String(&'static str),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OptValue {
+    Check(bool),
+    Spin(i64),
+    Button,
+    String(String),
+}
+
+// how uci spells an empty string option
+pub const EMPTY: &str = "<empty>";
+
+// everything advertised after uci, in order
+pub const OPTIONS: [(&str, OptKind); 8] = [
+    (
+        "Hash",
+        OptKind::Spin {
+            default: 32,
+            min: 1,
+            max: 4096,
+        },
+    ),
+    ("Clear Hash", OptKind::Button),
+    (
+        "Threads",
+        OptKind::Spin {
+            default: 1,
+            min: 1,
+            max: 256,
+        },
+    ),
+    (
+        "MultiPV",
+        OptKind::Spin {
+            default: 1,
+            min: 1,
+            max: 256,
+        },
+    ),
+    (
+        "Move Overhead",
+        OptKind::Spin {
+            default: 30,
+            min: 0,
+            max: 5000,
+        },
+    ),
+    ("Ponder", OptKind::Check(false)),
+    ("UCI_Chess960", OptKind::Check(false)),
+    // eval weights in the format EvalParams is written in
+    ("EvalFile", OptKind::String(EMPTY)),
+];
+
+pub struct UciOption(&'static str, OptKind);
+
+impl fmt::Display for UciOption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "option name {} type ", self.0)?;
+        match self.1 {
+            OptKind::Check(default) => write!(f, "check default {}", default),
+            OptKind::Spin { default, min, max } => {
+                write!(f, "spin default {} min {} max {}", default, min, max)
+            }
+            OptKind::Button => write!(f, "button"),
+            OptKind::String(default) => write!(f, "string default {}", default),
+        }
+    }
+}
+
+pub fn advertise() -> impl Iterator<Item = UciOption> {
+    OPTIONS.iter().map(|&(name, kind)| UciOption(name, kind))
+}
+
+// reads "<name> [value <x>]" from a setoption command. names are case
+// insensitive, values are checked against the option's type and range
+pub fn parse_option(rem: &str) -> Result<(&'static str, OptValue), String> {
+    let (name, value) = match rem.find(" value") {
+        Some(i) => (&rem[..i], Some(rem[i + " value".len()..].trim())),
+        None => (rem, None),
+    };
+    let name = name.trim();
+    let &(name, kind) = OPTIONS
+        .iter()
+        .find(|(opt, _)| opt.eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("no such option: {}", name))?;
+    let value = match (kind, value) {
+        (OptKind::Button, _) => OptValue::Button,
+        (OptKind::Check(_), Some("true")) => OptValue::Check(true),
+        (OptKind::Check(_), Some("false")) => OptValue::Check(false),
+        (OptKind::String(_), Some(v)) => OptValue::String(v.to_string()),
+        (OptKind::Spin { min, max, .. }, Some(v)) => match str::parse(v) {
+            Ok(n) if n >= min && n <= max => OptValue::Spin(n),
+            _ => {
+                return Err(format!(
+                    "{} must be a number from {} to {}, got {}",
+                    name, min, max, v
+                ))
+            }
+        },
+        (_, v) => return Err(format!("bad value for {}: {}", name, v.unwrap_or(""))),
+    };
+    Ok((name, value))
+}
+
+// current values of everything in OPTIONS
+#[derive(Clone, Debug, PartialEq)]
+pub struct UciOptions {
+    // transposition table size in megabytes
+    pub hash: usize,
+    pub threads: usize,
+    pub multi_pv: usize,
+    pub move_overhead: Duration,
+    pub ponder: bool,
+    // castling is sent and read as king takes rook
+    pub chess960: bool,
+    // None for the built-in eval weights
+    pub eval_file: Option<String>,
+}
+
+impl Default for UciOptions {
+    fn default() -> Self {
+        let mut options = UciOptions {
+            hash: 0,
+            threads: 0,
+            multi_pv: 0,
+            move_overhead: Duration::default(),
+            ponder: false,
+            chess960: false,
+            eval_file: None,
+        };
+        for &(name, kind) in &OPTIONS {
+            let value = match kind {
+                OptKind::Check(default) => OptValue::Check(default),
+                OptKind::Spin { default, .. } => OptValue::Spin(default),
+                OptKind::Button => OptValue::Button,
+                OptKind::String(default) => OptValue::String(default.to_string()),
+            };
+            options.set(name, value);
+        }
+        options
+    }
+}
+
+impl UciOptions {
+    // name and value are assumed to come from parse_option
+    pub fn set(&mut self, name: &str, value: OptValue) {
+        match (name, value) {
+            ("Hash", OptValue::Spin(n)) => self.hash = n as usize,
+            ("Threads", OptValue::Spin(n)) => self.threads = n as usize,
+            ("MultiPV", OptValue::Spin(n)) => self.multi_pv = n as usize,
+            ("Move Overhead", OptValue::Spin(n)) => {
+                self.move_overhead = Duration::from_millis(n as u64)
+            }
+            ("Ponder", OptValue::Check(b)) => self.ponder = b,
+            ("UCI_Chess960", OptValue::Check(b)) => self.chess960 = b,
+            ("EvalFile", OptValue::String(s)) => {
+                self.eval_file = Some(s).filter(|s| !s.is_empty() && s != EMPTY)
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_options() {
+        assert_eq!(
+            parse_option("Hash value 64"),
+            Ok(("Hash", OptValue::Spin(64)))
+        );
+        assert_eq!(
+            parse_option("move overhead value 100"),
+            Ok(("Move Overhead", OptValue::Spin(100)))
+        );
+        assert_eq!(
+            parse_option("Clear Hash"),
+            Ok(("Clear Hash", OptValue::Button))
+        );
+        assert_eq!(
+            parse_option("UCI_Chess960 value true"),
+            Ok(("UCI_Chess960", OptValue::Check(true)))
+        );
+        assert_eq!(
+            parse_option("EvalFile value my weights.txt"),
+            Ok(("EvalFile", OptValue::String("my weights.txt".to_string())))
+        );
+        assert!(parse_option("EvalFile").is_err());
+        assert!(parse_option("Hash value 0").is_err());
+        assert!(parse_option("Hash value lots").is_err());
+        assert!(parse_option("Threads").is_err());
+        assert!(parse_option("Ponder value yes").is_err());
+        assert!(parse_option("Contempt value 10").is_err());
+    }
+    #[test]
+    fn defaults() {
+        let mut options = UciOptions::default();
+        assert_eq!(options.hash, 32);
+        assert_eq!(options.move_overhead, Duration::from_millis(30));
+        assert!(!options.chess960);
+        let (name, value) = parse_option("multipv value 3").unwrap();
+        options.set(name, value);
+        assert_eq!(options.multi_pv, 3);
+        assert_eq!(options.eval_file, None);
+        options.set("EvalFile", OptValue::String("weights.txt".to_string()));
+        assert_eq!(options.eval_file.as_deref(), Some("weights.txt"));
+        options.set("EvalFile", OptValue::String(EMPTY.to_string()));
+        assert_eq!(options.eval_file, None);
+
+        let lines: Vec<_> = advertise().map(|o| o.to_string()).collect();
+        assert_eq!(
+            lines[0],
+            "option name Hash type spin default 32 min 1 max 4096"
+        );
+        assert_eq!(lines[1], "option name Clear Hash type button");
+        assert_eq!(lines[5], "option name Ponder type check default false");
+        assert_eq!(lines[7], "option name EvalFile type string default <empty>");
+    }
+}
@@ -1,5 +1,10 @@
-use engine::{EngineMsg, Searcher, StdSignal};
+use crate::options::{self, UciOptions};
 
+use engine::{
+    EngineMsg, Ponder, SearchLimits, Searcher, StdSignal, TimeControl, TimeManager, TransTable,
+};
+
+use futures::channel::oneshot;
 use futures::future::FutureExt;
 use futures::prelude::*;
 
@@ -7,20 +12,26 @@ use tokio::{task, time};
 
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use std::str::FromStr;
 use std::time::Duration;
 
-const TIME_MUL: f64 = 1.0 / 60.0;
-const INC_MUL: f64 = 0.97;
 const INF_DEPTH: i32 = 999;
+const INF_TIME: Duration = Duration::from_secs(365 * 24 * 60 * 60);
 
 pub struct UciState {
     stop: Arc<AtomicBool>,
     job: Option<future::BoxFuture<'static, ()>>,
     cancel: Option<Box<dyn FnOnce()>>,
+    // switches a go ponder search over to the clock
+    ponderhit: Option<Box<dyn FnOnce()>>,
     position: chess::State,
+    options: UciOptions,
+    // from EvalFile, given to every position
+    eval_params: Arc<chess::EvalParams>,
+    // kept between searches so the transposition table carries over
+    searcher: Arc<Mutex<Searcher>>,
     tx: mpsc::Sender<EngineMsg>,
 }
 
@@ -30,14 +41,48 @@ impl UciState {
             stop: Arc::new(AtomicBool::new(false)),
             job: None,
             cancel: None,
+            ponderhit: None,
             position: chess::State::default(),
+            options: Default::default(),
+            eval_params: Default::default(),
+            searcher: Arc::new(Mutex::new(new_searcher(&Default::default()))),
             tx,
         }
     }
 }
 
+fn new_searcher(options: &UciOptions) -> Searcher {
+    let mut searcher = Searcher::with_table(TransTable::with_mb(options.hash));
+    searcher.threads = options.threads;
+    searcher.multi_pv = options.multi_pv;
+    searcher
+}
+
+// reads an EvalFile, or gives the built-in weights without one
+fn load_params(file: Option<&str>) -> Result<chess::EvalParams, String> {
+    let file = match file {
+        Some(file) => file,
+        None => return Ok(Default::default()),
+    };
+    let text = std::fs::read_to_string(file).map_err(|e| format!("can't read {}: {}", file, e))?;
+    str::parse(&text).map_err(|e| format!("bad eval file {}: {}", file, e))
+}
+
+// consumes name from the front of rem if it's there as whole words, so
+// uci doesn't match ucinewgame
+fn take_cmd(rem: &mut &str, name: &str) -> bool {
+    match rem.strip_prefix(name) {
+        Some(rest) if rest.is_empty() || rest.starts_with(char::is_whitespace) => {
+            *rem = rest.trim_start();
+            true
+        }
+        _ => false,
+    }
+}
+
 impl UciState {
     pub async fn stop_job(&mut self) {
+        self.ponderhit = None;
         if let Some(cancel) = self.cancel.take() {
             cancel();
         }
@@ -57,95 +102,152 @@ impl UciState {
             EngineMsg::Info(info) => {
                 return send!("info {}", info);
             }
-            EngineMsg::BestMove(mv) => {
+            EngineMsg::BestMove(mv, Some(ponder)) => {
+                return send!("bestmove {} ponder {}", mv, ponder);
+            }
+            EngineMsg::BestMove(mv, None) => {
                 return send!("bestmove {}", mv);
             }
         };
 
         // remaining data not consumed by the command process
         let mut rem = buf.as_str().trim();
-        // kinda like strip_prefix if it was stable
-        let mut cmd = |name: &str| {
-            if rem.starts_with(name) {
-                rem = &rem[name.len()..].trim_start();
-                return true;
-            }
-            false
-        };
+        let mut cmd = |name: &str| take_cmd(&mut rem, name);
         fn parse_n<T: FromStr>(n: &str, def: T) -> T {
             str::parse(n).unwrap_or(def)
         }
         if cmd("uci") {
             send!("id name stubot {}", env!("CARGO_PKG_VERSION"));
             send!("id author Stuart Geipel");
+            for option in options::advertise() {
+                send!("{}", option);
+            }
             send!("uciok");
-        } else if cmd("debug") {
-            // nothing for now
         } else if cmd("isready") {
             send!("readyok");
         } else if cmd("setoption name") {
-            // nothing for now
-        } else if cmd("register") {
+            let (name, value) = match options::parse_option(rem) {
+                Ok(opt) => opt,
+                Err(e) => return send!("info string {}", e),
+            };
+            // the searcher can't change under a running search
+            self.stop_job().await;
+            self.options.set(name, value);
+            let mut searcher = self.searcher.lock().unwrap();
+            match name {
+                "Hash" => searcher.table = Arc::new(TransTable::with_mb(self.options.hash)),
+                "Clear Hash" => searcher.table.clear(),
+                "Threads" => searcher.threads = self.options.threads,
+                "MultiPV" => searcher.multi_pv = self.options.multi_pv,
+                "UCI_Chess960" => self.position.set_chess960(self.options.chess960),
+                "EvalFile" => match load_params(self.options.eval_file.as_deref()) {
+                    Ok(params) => {
+                        self.eval_params = Arc::new(params);
+                        self.position.set_eval_params(self.eval_params.clone());
+                        // everything stored was scored with the old weights
+                        searcher.table.clear();
+                        searcher.clear_pawn_table();
+                    }
+                    Err(e) => send!("info string {}", e),
+                },
+                _ => (),
+            }
+        } else if cmd("debug") || cmd("register") {
             // nothing for now
         } else if cmd("ucinewgame") {
+            self.stop_job().await;
             self.position = Default::default();
+            self.position.set_chess960(self.options.chess960);
+            self.position.set_eval_params(self.eval_params.clone());
+            self.searcher.lock().unwrap().table.clear();
         } else if cmd("position") {
             let parts: Vec<_> = rem.split(" moves ").collect();
-            self.position = match parts[0] {
+            // bad input leaves the old position alone
+            let mut position: chess::State = match parts[0] {
                 "startpos" => Default::default(),
-                s => {
-                    assert!(s.starts_with("fen "));
-                    str::parse(&s[4..]).unwrap()
-                }
+                s => match s.strip_prefix("fen ").map(str::parse) {
+                    Some(Ok(state)) => state,
+                    Some(Err(e)) => return send!("info string invalid fen: {}", e),
+                    None => return send!("info string expected startpos or fen"),
+                },
             };
+            position.set_chess960(self.options.chess960);
+            position.set_eval_params(self.eval_params.clone());
             if let Some(moves) = parts.get(1) {
-                self.position.run_moves(moves.split(" "));
+                if let Err(e) = position.try_run_moves(moves.split(' ')) {
+                    return send!("info string {}", e);
+                }
             }
+            self.position = position;
         } else if cmd("go") {
             self.stop_job().await;
 
             let mut args = rem.split_ascii_whitespace();
             let turn = self.position.turn().to_string();
-            // usually either time or depth is "infinite" but not both
-            let inf_time = Duration::from_secs(365 * 24 * 60 * 60);
-            let mut time = Default::default();
-            let parse_time = |ms: Option<&str>| Duration::from_millis(parse_n(ms.unwrap(), 0));
-            let mut depth = INF_DEPTH;
-
-            if rem.is_empty() {
-                time = inf_time;
-            }
+            let parse_time = |ms: Option<&str>| Duration::from_millis(parse_n(ms.unwrap_or(""), 0));
+            let mut control = TimeControl {
+                overhead: self.options.move_overhead,
+                ..Default::default()
+            };
+            let mut limits = SearchLimits::depth(INF_DEPTH);
+            let mut pondering = false;
+
             while let Some(arg) = args.next() {
-                if arg == "infinite" {
-                    time = inf_time;
+                if arg == "ponder" {
+                    pondering = true;
                 } else if arg == "movetime" {
-                    time += parse_time(args.next());
+                    control.move_time = Some(parse_time(args.next()));
                 } else if arg == format!("{}time", turn) {
-                    time += parse_time(args.next()).mul_f64(TIME_MUL);
+                    control.time = Some(parse_time(args.next()));
                 } else if arg == format!("{}inc", turn) {
-                    time += parse_time(args.next()).mul_f64(INC_MUL);
+                    control.inc = parse_time(args.next());
+                } else if arg == "movestogo" {
+                    control.moves_to_go = args.next().and_then(|n| str::parse(n).ok());
                 } else if arg == "depth" {
-                    time = inf_time;
-                    depth = parse_n(args.next().unwrap(), 0);
+                    limits.depth = parse_n(args.next().unwrap_or(""), INF_DEPTH);
+                } else if arg == "nodes" {
+                    limits.nodes = args.next().and_then(|n| str::parse(n).ok());
+                } else if arg == "mate" {
+                    limits.mate = args.next().and_then(|n| str::parse(n).ok());
                 }
             }
+            // untimed searches like infinite or depth go until stopped
+            let time = control.limits().map_or(INF_TIME, |(_, hard)| hard);
 
             self.stop.store(false, Ordering::Relaxed);
 
             let (abort_fut, abort_handle) = future::abortable(future::pending::<()>());
 
             let mut pos = self.position.clone();
-            let mut searcher = Searcher::new();
-            let signal = StdSignal::new(self.stop.clone(), self.tx.clone());
+            let searcher = self.searcher.clone();
+            let ponder = Arc::new(Ponder::new(pondering));
+            let signal = StdSignal::new(
+                self.stop.clone(),
+                self.tx.clone(),
+                TimeManager::new(&control),
+                ponder.clone(),
+            );
             let job_task = task::spawn_blocking(move || {
-                searcher.iter_negamax(&mut pos, depth, &signal);
+                let mut searcher = searcher.lock().unwrap();
+                searcher.nodes = 0;
+                searcher.search(&mut pos, limits, &signal);
             });
 
+            // the clock only starts at ponderhit when pondering
+            let (hit_tx, hit_rx) = oneshot::channel::<()>();
+            let timeout = async move {
+                if pondering {
+                    hit_rx.await.ok();
+                }
+                time::delay_for(time).await
+            };
+
             // abort or timeout, whichever happens first
             let stop = self.stop.clone();
-            let cancel_task = task::spawn(time::timeout(time, abort_fut).map(move |_| {
-                stop.store(true, Ordering::Relaxed);
-            }));
+            let cancel_task =
+                task::spawn(future::select(abort_fut, timeout.boxed()).map(move |_| {
+                    stop.store(true, Ordering::Relaxed);
+                }));
 
             // job future waits for cancel, (then stop should be true), then joins on job_task
             let job = cancel_task
@@ -159,12 +261,23 @@ impl UciState {
 
             self.job = Some(Box::pin(job));
             self.cancel = Some(Box::new(move || abort_handle.abort()));
+            self.ponderhit = Some(Box::new(move || {
+                ponder.hit();
+                hit_tx.send(()).ok();
+            }));
+        } else if cmd("ponderhit") {
+            // keep searching, but on our own clock now
+            if let Some(hit) = self.ponderhit.take() {
+                hit();
+            }
         } else if cmd("stop") {
             self.stop_job().await;
         } else if cmd("quit") {
             std::process::exit(0);
         } else if cmd("move") {
-            self.position.run_moves(rem.split(" "));
+            if let Err(e) = self.position.try_run_moves(rem.split(' ')) {
+                send!("info string {}", e);
+            }
         } else if cmd("safe_move") {
             // when unmake_move trashes the state, we can't trust movegen much
             let mut cpy = self.position.clone();
@@ -182,6 +295,22 @@ impl UciState {
                 }
                 self.position.unmake_move();
             }
+        } else if cmd("pgn") {
+            // the game so far, from the last position command
+            let game = pgn::Game::from_state(&self.position);
+            for line in game.to_string().lines() {
+                send!("{}", line);
+            }
+        } else if cmd("eval") {
+            // what the eval makes of the position, term by term
+            let trace = self.position.eval_trace();
+            if cmd("json") {
+                send!("{}", trace.to_json());
+            } else {
+                for line in trace.to_string().lines() {
+                    send!("{}", line);
+                }
+            }
         } else if cmd("pprint") {
             send!("{}", self.position.board_string());
         } else if cmd("perft") {
@@ -191,3 +320,22 @@ impl UciState {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn whole_words() {
+        let mut rem = "ucinewgame";
+        assert!(!take_cmd(&mut rem, "uci"));
+        assert!(take_cmd(&mut rem, "ucinewgame"));
+        assert_eq!(rem, "");
+        let mut rem = "setoption name Hash value 64";
+        assert!(!take_cmd(&mut rem, "setoption nam"));
+        assert!(take_cmd(&mut rem, "setoption name"));
+        assert_eq!(rem, "Hash value 64");
+        let mut rem = "eval json";
+        assert!(take_cmd(&mut rem, "eval") && take_cmd(&mut rem, "json"));
+    }
+}
@@ -2,7 +2,9 @@
 #![no_std]
 extern crate alloc;
 
+use alloc::boxed::Box;
 use alloc::string::*;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 
 use chess::*;
@@ -16,23 +18,69 @@ use wasm_bindgen::prelude::*;
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
+// transposition table entries
+const TABLE_SIZE: usize = 1 << 16;
+
 #[wasm_bindgen]
 pub struct WasmSearcher {
     searcher: Searcher,
+    // eval weights every searched state gets, None for the built-in ones
+    params: Option<Arc<EvalParams>>,
 }
 
+impl WasmSearcher {
+    fn use_params(&self, state: &mut WasmState) {
+        if let Some(params) = &self.params {
+            state.state.set_eval_params(params.clone());
+        }
+    }
+}
 #[wasm_bindgen]
 impl WasmSearcher {
+    // params is the text of an eval file, like the uci EvalFile option
+    // reads. a bad one throws instead of aborting the module
     #[wasm_bindgen(constructor)]
-    pub fn new() -> WasmSearcher {
-        let searcher = Searcher::new();
-        WasmSearcher { searcher }
+    pub fn new(params: Option<String>) -> Result<WasmSearcher, JsValue> {
+        let searcher = Searcher::new(TABLE_SIZE);
+        let params = params
+            .map(|s| str::parse::<EvalParams>(&s))
+            .transpose()
+            .map_err(|e| JsValue::from(e.to_string()))?
+            .map(Arc::new);
+        Ok(WasmSearcher { searcher, params })
     }
     pub fn search(&mut self, mut state: WasmState, depth: i32) -> SearchResult {
+        self.use_params(&mut state);
         let (mv, score) = self
             .searcher
             .iter_negamax(&mut state.state, depth, &BlockSignal {});
-        SearchResult { score, mv }
+        let pv = self.searcher.pv().to_vec();
+        SearchResult { score, mv, pv }
+    }
+    // the best `lines` moves, each with its own score and pv, best first
+    #[wasm_bindgen(js_name=searchMulti)]
+    pub fn search_multi(
+        &mut self,
+        mut state: WasmState,
+        depth: i32,
+        lines: usize,
+    ) -> Box<[JsValue]> {
+        self.use_params(&mut state);
+        self.searcher.multi_pv = lines.max(1);
+        self.searcher
+            .iter_negamax(&mut state.state, depth, &BlockSignal {});
+        self.searcher.multi_pv = 1;
+        self.searcher
+            .lines()
+            .iter()
+            .map(|&((mv, score), ref pv)| {
+                JsValue::from(SearchResult {
+                    score,
+                    mv,
+                    pv: pv.clone(),
+                })
+            })
+            .collect()
     }
     #[wasm_bindgen(getter)]
     pub fn nodes(&self) -> f64 {
@@ -77,6 +125,11 @@ impl WasmState {
     pub fn score(&mut self) -> i16 {
         self.state.slow_score()
     }
+    // every term of the eval for both sides, as json
+    #[wasm_bindgen(js_name=evalTrace)]
+    pub fn eval_trace(&self) -> String {
+        self.state.eval_trace().to_json()
+    }
     #[wasm_bindgen(js_name=boardString)]
     pub fn board_string(&self) -> String {
         self.state.board_string()
@@ -108,6 +161,7 @@ impl WasmPos {
 pub struct SearchResult {
     pub score: i16,
     mv: Option<Move>,
+    pv: Vec<Move>,
 }
 #[wasm_bindgen]
 impl SearchResult {
@@ -115,6 +169,11 @@ impl SearchResult {
     pub fn mv(&self) -> Option<String> {
         self.mv.map(|m| m.to_string())
     }
+    // space separated, like a uci info line
+    #[wasm_bindgen(getter)]
+    pub fn pv(&self) -> String {
+        show_iter(|mv| mv.to_string(), " ", &self.pv)
+    }
 }
 
 #[wasm_bindgen(start)]
@@ -151,7 +210,7 @@ impl WasmSearcher {
     pub fn poll(&self) -> String {
         loop {
             match self.rx.try_recv() {
-                Ok(EngineMsg::BestMove(best)) => break best.to_string(),
+                Ok(EngineMsg::BestMove(best, _)) => break best.to_string(),
                 Ok(_) => (),
                 Err(Empty) => break "pending".to_string(),
                 Err(Disconnected) => panic!()